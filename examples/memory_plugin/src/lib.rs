@@ -0,0 +1,4 @@
+//! The in-memory store of `rust-kvstore-bench` built as a plugin, loaded by its `plugin`
+//! backend with `--options '{"library":"./target/release/libmemory_plugin.so"}'`.
+
+rust_kvstore_bench::export_plugin!(rust_kvstore_bench::memory::Store);
@@ -0,0 +1,95 @@
+use crate::Error;
+use std::collections::BTreeMap;
+use std::ops::Bound;
+
+enum Write {
+    Put(Vec<u8>, Vec<u8>),
+    Delete(Vec<u8>),
+    DeleteRange(Vec<u8>, Vec<u8>),
+}
+
+/// The writes of a batch of an engine without range deletes from its first range delete,
+/// which are replayed when committing, so that a range delete removes the keys of the store
+/// at that time, in the timed commit. The writes before it stay in the batch of the engine,
+/// where the range delete cancels the puts of its keys.
+#[derive(Default)]
+pub struct Writes {
+    writes: Vec<Write>,
+}
+
+impl Writes {
+    pub fn put(&mut self, key: &[u8], value: &[u8]) {
+        self.writes.push(Write::Put(key.to_vec(), value.to_vec()));
+    }
+
+    pub fn delete(&mut self, key: &[u8]) {
+        self.writes.push(Write::Delete(key.to_vec()));
+    }
+
+    pub fn delete_range(&mut self, start: &[u8], end: &[u8]) {
+        self.writes
+            .push(Write::DeleteRange(start.to_vec(), end.to_vec()));
+    }
+
+    // the last value written to each key, None for a delete, where `keys` returns the keys
+    // of the store from `start` included to `end` excluded
+    pub fn changes<F>(self, mut keys: F) -> Result<BTreeMap<Vec<u8>, Option<Vec<u8>>>, Error>
+    where
+        F: FnMut(&[u8], &[u8]) -> Result<Vec<Vec<u8>>, Error>,
+    {
+        let mut changes = BTreeMap::new();
+        for write in self.writes {
+            match write {
+                Write::Put(key, value) => {
+                    changes.insert(key, Some(value));
+                }
+                Write::Delete(key) => {
+                    changes.insert(key, None);
+                }
+                Write::DeleteRange(start, end) => {
+                    if start >= end {
+                        continue;
+                    }
+                    let range = (Bound::Included(&start[..]), Bound::Excluded(&end[..]));
+                    for (_, value) in changes.range_mut::<[u8], _>(range) {
+                        *value = None;
+                    }
+                    for key in keys(&start, &end)? {
+                        changes.insert(key, None);
+                    }
+                }
+            }
+        }
+        Ok(changes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn range_deletes_follow_the_writes() {
+        let mut writes = Writes::default();
+        writes.put(&[1], &[1]);
+        writes.put(&[4], &[4]);
+        writes.delete_range(&[0], &[3]);
+        writes.put(&[2], &[2]);
+        writes.delete_range(&[3], &[3]);
+        let changes = writes
+            .changes(|start, end| {
+                assert_eq!((&[0][..], &[3][..]), (start, end));
+                Ok(vec![vec![0]])
+            })
+            .unwrap();
+        assert_eq!(
+            vec![
+                (vec![0], None),
+                (vec![1], None),
+                (vec![2], Some(vec![2])),
+                (vec![4], Some(vec![4])),
+            ],
+            changes.into_iter().collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,662 @@
+use crate::{counter, Batch, Error, KeyValue, KeyValueStore, Snapshot};
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::{env, fs, process};
+
+/// Size of the large keys, the largest key of LMDB.
+pub const LARGE_KEY_SIZE: usize = 511;
+/// Size of the large values.
+pub const LARGE_VALUE_SIZE: usize = 1 << 20;
+
+/// What the checks expect of a store beyond the semantics of `KeyValueStore`.
+#[derive(Debug, Clone, Copy)]
+pub struct Capabilities {
+    // whether a store opened again by the opener of a check has the pairs of the previous one
+    pub persistent: bool,
+    // whether the store takes the empty key, LMDB doesn't
+    pub empty_key: bool,
+    // whether the keys are in order for the scans and the range deletes, which fail
+    // otherwise, as in a parity-db hash column
+    pub ordered: bool,
+    // whether `snapshot` is a point-in-time view, it fails otherwise
+    pub snapshots: bool,
+    // whether `compare_and_swap` is atomic, it fails otherwise, as in plain rocksdb
+    pub compare_and_swap: bool,
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Self {
+            persistent: true,
+            empty_key: true,
+            ordered: true,
+            snapshots: true,
+            compare_and_swap: true,
+        }
+    }
+}
+
+/// Generates a `#[test]` for each check of the module on the store `$store`, so that a
+/// backend only has to write the tests of what is specific to it, e.g.
+///
+/// ```ignore
+/// rust_kvstore_bench::conformance_tests!(my_store::Store);
+/// rust_kvstore_bench::conformance_tests!(my_store::Store, Capabilities { empty_key: false, ..Capabilities::default() });
+/// ```
+///
+/// By default each test opens the store with `new` at a new temporary directory, and
+/// reopens it there. The opener can be given instead, it's called with the directory and
+/// returns the function which opens the store, e.g. which connects to a server.
+#[macro_export]
+macro_rules! conformance_tests {
+    ($store:ty) => {
+        $crate::conformance_tests!($store, $crate::conformance::Capabilities::default());
+    };
+    ($store:ty, $capabilities:expr) => {
+        $crate::conformance_tests!($store, $crate::conformance::reopen::<$store>, $capabilities);
+    };
+    ($store:ty, $open:expr, $capabilities:expr) => {
+        $crate::conformance_tests!(@tests $store, $open, $capabilities,
+            conformance_put_and_get => put_and_get,
+            conformance_overwrite => overwrite,
+            conformance_delete => delete,
+            conformance_empty_key_and_value => empty_key_and_value,
+            conformance_large_pairs => large_pairs,
+            conformance_batch_atomicity => batch_atomicity,
+            conformance_reopen => reopen_keeps_pairs,
+            conformance_scan_order => scan_order,
+            conformance_delete_range => delete_range_and_scan,
+            conformance_snapshot => snapshot,
+            conformance_compare_and_swap_and_add => compare_and_swap_and_add,
+        );
+    };
+    (@tests $store:ty, $open:expr, $capabilities:expr, $($test:ident => $check:ident,)*) => {
+        $(
+            #[test]
+            fn $test() {
+                $crate::conformance::run::<$store, _, _>(
+                    stringify!($check),
+                    $open,
+                    $crate::conformance::$check::<$store>,
+                    &$capabilities,
+                );
+            }
+        )*
+    };
+}
+
+/// The default opener of `conformance_tests!`, which opens the store with `new` at `path`.
+pub fn reopen<T>(path: &str) -> impl Fn() -> T
+where
+    T: for<'a> KeyValueStore<'a>,
+{
+    let path = path.to_string();
+    move || T::new(&path)
+}
+
+/// Runs `check` with the store of `open` at a new temporary directory, which is removed
+/// afterwards, even when the check fails.
+pub fn run<T, O, R>(
+    name: &str,
+    open: O,
+    check: fn(&dyn Fn() -> T, &Capabilities),
+    capabilities: &Capabilities,
+) where
+    O: FnOnce(&str) -> R,
+    R: Fn() -> T,
+{
+    let dir = TempDir::new(name);
+    let open = open(dir.0.to_str().unwrap());
+    check(&open, capabilities);
+}
+
+// a new directory for each run, tempfile is only a dev-dependency
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> Self {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let path = env::temp_dir().join(format!(
+            "conformance-{}-{}-{}",
+            name,
+            process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+fn write<T>(store: &T, puts: &[(&[u8], &[u8])], deletes: &[&[u8]])
+where
+    T: for<'a> KeyValueStore<'a>,
+{
+    let mut batch = store.batch().unwrap();
+    for (key, value) in puts {
+        batch.put(key, value).unwrap();
+    }
+    for key in deletes {
+        batch.delete(key).unwrap();
+    }
+    batch.commit().unwrap();
+}
+
+/// The pairs of a batch are read by `get`, `multi_get` and `exists`.
+pub fn put_and_get<T>(open: &dyn Fn() -> T, _: &Capabilities)
+where
+    T: for<'a> KeyValueStore<'a>,
+{
+    let store = open();
+    assert!(!store.exists(&[0, 0]).unwrap());
+    write(&store, &[(&[0, 0], &[0, 0, 0]), (&[1, 1], &[1, 1, 1])], &[]);
+
+    assert_eq!(Some(vec![0, 0, 0]), store.get(&[0, 0]).unwrap());
+    assert_eq!(Some(vec![1, 1, 1]), store.get(&[1, 1]).unwrap());
+    assert_eq!(None, store.get(&[2, 2]).unwrap());
+    assert_eq!(None, store.get(&[0]).unwrap());
+    assert!(store.exists(&[0, 0]).unwrap());
+    assert!(!store.exists(&[2, 2]).unwrap());
+    assert_eq!(
+        vec![
+            Some(vec![1, 1, 1]),
+            None,
+            Some(vec![0, 0, 0]),
+            Some(vec![1, 1, 1])
+        ],
+        store
+            .multi_get(&[&[1, 1], &[2, 2], &[0, 0], &[1, 1]])
+            .unwrap()
+    );
+    assert!(store.multi_get(&[]).unwrap().is_empty());
+}
+
+/// A put replaces the value of the key, the last put of a batch wins.
+pub fn overwrite<T>(open: &dyn Fn() -> T, _: &Capabilities)
+where
+    T: for<'a> KeyValueStore<'a>,
+{
+    let store = open();
+    write(&store, &[(&[0], &[0, 0, 0])], &[]);
+    write(&store, &[(&[0], &[1])], &[]);
+    assert_eq!(Some(vec![1]), store.get(&[0]).unwrap());
+
+    write(&store, &[(&[1], &[1]), (&[1], &[2, 2]), (&[1], &[3])], &[]);
+    assert_eq!(Some(vec![3]), store.get(&[1]).unwrap());
+}
+
+/// A delete removes the key, deleting a missing key does nothing, and the deletes of a
+/// batch come after its puts here.
+pub fn delete<T>(open: &dyn Fn() -> T, _: &Capabilities)
+where
+    T: for<'a> KeyValueStore<'a>,
+{
+    let store = open();
+    write(&store, &[(&[0, 0], &[0, 0, 0]), (&[1, 1], &[1, 1, 1])], &[]);
+    write(&store, &[], &[&[0, 0], &[2, 2]]);
+    assert_eq!(None, store.get(&[0, 0]).unwrap());
+    assert!(!store.exists(&[0, 0]).unwrap());
+    assert!(!store.exists(&[2, 2]).unwrap());
+    assert_eq!(Some(vec![1, 1, 1]), store.get(&[1, 1]).unwrap());
+
+    // deleted twice, then put and deleted in the same batch
+    write(&store, &[(&[3], &[3])], &[&[0, 0], &[3]]);
+    assert_eq!(None, store.get(&[0, 0]).unwrap());
+    assert_eq!(None, store.get(&[3]).unwrap());
+
+    // deleted, then put again
+    let mut batch = store.batch().unwrap();
+    batch.delete(&[1, 1]).unwrap();
+    batch.put(&[1, 1], &[1]).unwrap();
+    batch.commit().unwrap();
+    assert_eq!(Some(vec![1]), store.get(&[1, 1]).unwrap());
+}
+
+/// An empty value is a value, not a missing key, and so is the empty key when the store
+/// takes it.
+pub fn empty_key_and_value<T>(open: &dyn Fn() -> T, capabilities: &Capabilities)
+where
+    T: for<'a> KeyValueStore<'a>,
+{
+    let store = open();
+    write(&store, &[(&[0], &[])], &[]);
+    assert_eq!(Some(vec![]), store.get(&[0]).unwrap());
+    assert!(store.exists(&[0]).unwrap());
+    assert_eq!(vec![Some(vec![])], store.multi_get(&[&[0]]).unwrap());
+    if capabilities.ordered {
+        assert_eq!(vec![(vec![0], vec![])], store.scan(&[0], 10).unwrap());
+    }
+
+    if capabilities.empty_key {
+        assert!(!store.exists(&[]).unwrap());
+        write(&store, &[(&[], &[1])], &[]);
+        assert_eq!(Some(vec![1]), store.get(&[]).unwrap());
+        if capabilities.ordered {
+            assert_eq!(
+                vec![(vec![], vec![1]), (vec![0], vec![])],
+                store.scan(&[], 10).unwrap()
+            );
+        }
+        write(&store, &[], &[&[]]);
+        assert!(!store.exists(&[]).unwrap());
+    }
+}
+
+/// Keys of `LARGE_KEY_SIZE` bytes and values of `LARGE_VALUE_SIZE` bytes.
+pub fn large_pairs<T>(open: &dyn Fn() -> T, capabilities: &Capabilities)
+where
+    T: for<'a> KeyValueStore<'a>,
+{
+    let store = open();
+    let key: Vec<u8> = (0..LARGE_KEY_SIZE).map(|i| i as u8).collect();
+    let value: Vec<u8> = (0..LARGE_VALUE_SIZE).map(|i| (i * 7) as u8).collect();
+    write(&store, &[(&key, &value), (&[0], &value[1..])], &[]);
+
+    assert_eq!(Some(value.clone()), store.get(&key).unwrap());
+    assert_eq!(
+        vec![Some(value[1..].to_vec()), Some(value.clone())],
+        store.multi_get(&[&[0], &key]).unwrap()
+    );
+    if capabilities.ordered {
+        assert_eq!(vec![(key.clone(), value)], store.scan(&key, 10).unwrap());
+    }
+
+    write(&store, &[], &[&key]);
+    assert!(!store.exists(&key).unwrap());
+}
+
+/// The writes of a batch are seen together once it's committed, and a batch dropped
+/// without a commit has no effect.
+pub fn batch_atomicity<T>(open: &dyn Fn() -> T, capabilities: &Capabilities)
+where
+    T: for<'a> KeyValueStore<'a>,
+{
+    let store = open();
+    write(&store, &[(&[0], &[0])], &[]);
+
+    let mut batch = store.batch().unwrap();
+    batch.put(&[1], &[1]).unwrap();
+    batch.delete(&[0]).unwrap();
+    assert_eq!(
+        vec![Some(vec![0]), None],
+        store.multi_get(&[&[0], &[1]]).unwrap()
+    );
+    drop(batch);
+    assert_eq!(
+        vec![Some(vec![0]), None],
+        store.multi_get(&[&[0], &[1]]).unwrap()
+    );
+
+    let mut batch = store.batch().unwrap();
+    batch.put(&[1], &[1]).unwrap();
+    batch.delete(&[0]).unwrap();
+    batch.put(&[2], &[2]).unwrap();
+    assert_eq!(
+        vec![Some(vec![0]), None, None],
+        store.multi_get(&[&[0], &[1], &[2]]).unwrap()
+    );
+    batch.commit().unwrap();
+    assert_eq!(
+        vec![None, Some(vec![1]), Some(vec![2])],
+        store.multi_get(&[&[0], &[1], &[2]]).unwrap()
+    );
+    if capabilities.ordered {
+        assert_eq!(
+            vec![(vec![1], vec![1]), (vec![2], vec![2])],
+            store.scan(&[], 10).unwrap()
+        );
+    }
+}
+
+/// A store opened again has the pairs committed before it was closed, when it's persistent.
+pub fn reopen_keeps_pairs<T>(open: &dyn Fn() -> T, capabilities: &Capabilities)
+where
+    T: for<'a> KeyValueStore<'a>,
+{
+    if !capabilities.persistent {
+        return;
+    }
+    {
+        let store = open();
+        write(&store, &[(&[0], &[0]), (&[1], &[1]), (&[2], &[2])], &[]);
+        write(&store, &[(&[1], &[1, 1])], &[&[2]]);
+    }
+    {
+        let store = open();
+        assert_eq!(
+            vec![Some(vec![0]), Some(vec![1, 1]), None],
+            store.multi_get(&[&[0], &[1], &[2]]).unwrap()
+        );
+        if capabilities.ordered {
+            assert_eq!(
+                vec![(vec![0], vec![0]), (vec![1], vec![1, 1])],
+                store.scan(&[], 10).unwrap()
+            );
+        }
+        write(&store, &[(&[3], &[3])], &[&[0]]);
+    }
+    let store = open();
+    assert_eq!(
+        vec![None, Some(vec![1, 1]), None, Some(vec![3])],
+        store.multi_get(&[&[0], &[1], &[2], &[3]]).unwrap()
+    );
+}
+
+/// `scan` returns the pairs in the byte order of their keys, a key before its extensions,
+/// from the first key at or after `start`.
+pub fn scan_order<T>(open: &dyn Fn() -> T, capabilities: &Capabilities)
+where
+    T: for<'a> KeyValueStore<'a>,
+{
+    let store = open();
+    if !capabilities.ordered {
+        assert!(store.scan(&[], 10).is_err());
+        return;
+    }
+    let keys: &[&[u8]] = &[
+        &[2],
+        &[1, 0],
+        &[0, 255],
+        &[1],
+        &[255],
+        &[1, 0, 0],
+        &[0, 0, 1],
+    ];
+    let puts: Vec<(&[u8], &[u8])> = keys.iter().map(|key| (*key, *key)).collect();
+    write(&store, &puts, &[]);
+
+    let mut sorted: Vec<Vec<u8>> = keys.iter().map(|key| key.to_vec()).collect();
+    sorted.sort();
+    let pairs = |keys: &[Vec<u8>]| -> Vec<(Vec<u8>, Vec<u8>)> {
+        keys.iter().map(|key| (key.clone(), key.clone())).collect()
+    };
+    assert_eq!(pairs(&sorted), store.scan(&[0], 100).unwrap());
+    assert_eq!(pairs(&sorted[..3]), store.scan(&[0], 3).unwrap());
+    // from a missing key, and from an extension of a key
+    assert_eq!(pairs(&sorted[2..5]), store.scan(&[0, 255, 0], 3).unwrap());
+    assert_eq!(pairs(&sorted[2..]), store.scan(&[1], 100).unwrap());
+    assert_eq!(pairs(&sorted[6..]), store.scan(&[254], 100).unwrap());
+    assert!(store.scan(&[255, 0], 100).unwrap().is_empty());
+    assert!(store.scan(&[0], 0).unwrap().is_empty());
+}
+
+/// A range delete removes the keys from `start` included to `end` excluded, the ones
+/// committed before its batch and the ones put before it in its batch.
+pub fn delete_range_and_scan<T>(open: &dyn Fn() -> T, capabilities: &Capabilities)
+where
+    T: for<'a> KeyValueStore<'a>,
+{
+    let store = open();
+    store
+        .bulk_load((0..5u8).map(|i| (vec![i], vec![i])))
+        .unwrap();
+    let mut batch = store.batch().unwrap();
+    batch.put(&[2, 0], &[2, 0]).unwrap();
+    if !capabilities.ordered {
+        assert!(batch.delete_range(&[1], &[3]).is_err() || batch.commit().is_err());
+        assert_eq!(Some(vec![1]), store.get(&[1]).unwrap());
+        return;
+    }
+    batch.delete_range(&[1], &[3]).unwrap();
+    batch.put(&[5], &[5]).unwrap();
+    batch.commit().unwrap();
+
+    assert_eq!(None, store.get(&[1]).unwrap());
+    assert!(!store.exists(&[2, 0]).unwrap());
+    assert_eq!(
+        vec![(vec![0], vec![0]), (vec![3], vec![3])],
+        store.scan(&[], 2).unwrap()
+    );
+    assert_eq!(
+        vec![(vec![4], vec![4]), (vec![5], vec![5])],
+        store.scan(&[4], 10).unwrap()
+    );
+}
+
+/// A snapshot reads the pairs committed before it was taken, not the later ones, when the
+/// store has snapshots.
+pub fn snapshot<T>(open: &dyn Fn() -> T, capabilities: &Capabilities)
+where
+    T: for<'a> KeyValueStore<'a>,
+{
+    let store = open();
+    write(&store, &[(&[0, 0], &[0, 0, 0])], &[]);
+    if !capabilities.snapshots {
+        assert!(store.snapshot().is_err());
+        return;
+    }
+
+    let snapshot = store.snapshot().unwrap();
+    write(&store, &[(&[0, 0], &[1, 1, 1]), (&[1, 1], &[1, 1, 1])], &[]);
+    assert_eq!(Some(vec![0, 0, 0]), snapshot.get(&[0, 0]).unwrap());
+    assert!(!snapshot.exists(&[1, 1]).unwrap());
+    assert_eq!(
+        vec![Some(vec![0, 0, 0]), None],
+        snapshot.multi_get(&[&[0, 0], &[1, 1]]).unwrap()
+    );
+    assert_eq!(Some(vec![1, 1, 1]), store.get(&[0, 0]).unwrap());
+}
+
+/// `compare_and_swap` writes only when the value is the expected one, None being a missing
+/// key, and `add` sums the deltas of a counter.
+pub fn compare_and_swap_and_add<T>(open: &dyn Fn() -> T, capabilities: &Capabilities)
+where
+    T: for<'a> KeyValueStore<'a>,
+{
+    let store = open();
+    if capabilities.compare_and_swap {
+        assert!(store.compare_and_swap(&[0, 0], None, Some(&[0])).unwrap());
+        assert!(!store.compare_and_swap(&[0, 0], None, Some(&[1])).unwrap());
+        assert!(store
+            .compare_and_swap(&[0, 0], Some(&[0]), Some(&[1]))
+            .unwrap());
+        assert_eq!(Some(vec![1]), store.get(&[0, 0]).unwrap());
+        assert!(!store.compare_and_swap(&[0, 0], Some(&[0]), None).unwrap());
+        assert!(store.compare_and_swap(&[0, 0], Some(&[1]), None).unwrap());
+    } else {
+        assert!(store.compare_and_swap(&[0, 0], None, Some(&[0])).is_err());
+    }
+    assert_eq!(None, store.get(&[0, 0]).unwrap());
+
+    store.add(&[1, 1], 3).unwrap();
+    store.add(&[1, 1], 4).unwrap();
+    assert_eq!(7, counter(store.get(&[1, 1]).unwrap().as_deref()));
+}
+
+/// A step of `check_model`.
+#[derive(Debug, Clone)]
+pub enum Operation {
+    Get(Vec<u8>),
+    Exists(Vec<u8>),
+    Scan(Vec<u8>, usize),
+    Batch(Vec<Write>),
+    // closes the store and opens it again
+    Reopen,
+}
+
+/// A write of the batch of `Operation::Batch`.
+#[derive(Debug, Clone)]
+pub enum Write {
+    Put(Vec<u8>, Vec<u8>),
+    Delete(Vec<u8>),
+    DeleteRange(Vec<u8>, Vec<u8>),
+}
+
+/// Runs `operations` on the store of `open` and on a `BTreeMap`, fails at the first step
+/// whose result differs, or after which the pairs of the store differ. The map is
+/// cleared by a reopen when the store isn't persistent.
+pub fn check_model<T>(
+    open: &dyn Fn() -> T,
+    capabilities: &Capabilities,
+    operations: &[Operation],
+) -> Result<(), String>
+where
+    T: for<'a> KeyValueStore<'a>,
+{
+    let mut model = BTreeMap::new();
+    let mut store = open();
+    for (step, operation) in operations.iter().enumerate() {
+        let fail = |message: String| format!("step {}, {:?}: {}", step, operation, message);
+        match operation {
+            Operation::Get(key) => expect(model.get(key).cloned(), store.get(key)).map_err(fail)?,
+            Operation::Exists(key) => {
+                expect(model.contains_key(key), store.exists(key)).map_err(fail)?
+            }
+            Operation::Scan(start, count) => expect(
+                pairs(model.range(start.clone()..).take(*count)),
+                store.scan(start, *count),
+            )
+            .map_err(fail)?,
+            Operation::Batch(writes) => {
+                commit(&store, writes).map_err(|e| fail(format!("the batch failed: {:?}", e)))?;
+                for write in writes {
+                    match write {
+                        Write::Put(key, value) => {
+                            model.insert(key.clone(), value.clone());
+                        }
+                        Write::Delete(key) => {
+                            model.remove(key);
+                        }
+                        Write::DeleteRange(start, end) => {
+                            model.retain(|key, _| key < start || key >= end);
+                        }
+                    }
+                }
+            }
+            Operation::Reopen => {
+                drop(store);
+                store = open();
+                if !capabilities.persistent {
+                    model.clear();
+                }
+            }
+        }
+        expect(pairs(model.iter()), store.scan(&[], model.len() + 1))
+            .map_err(|message| fail(format!("after it, {}", message)))?;
+    }
+    Ok(())
+}
+
+fn commit<T>(store: &T, writes: &[Write]) -> Result<(), Error>
+where
+    T: for<'a> KeyValueStore<'a>,
+{
+    let mut batch = store.batch()?;
+    for write in writes {
+        match write {
+            Write::Put(key, value) => batch.put(key, value)?,
+            Write::Delete(key) => batch.delete(key)?,
+            Write::DeleteRange(start, end) => batch.delete_range(start, end)?,
+        }
+    }
+    batch.commit()
+}
+
+fn pairs<'m, I>(pairs: I) -> Vec<KeyValue>
+where
+    I: Iterator<Item = (&'m Vec<u8>, &'m Vec<u8>)>,
+{
+    pairs
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect()
+}
+
+fn expect<V: PartialEq + Debug>(model: V, store: Result<V, Error>) -> Result<(), String> {
+    match store {
+        Ok(value) if value == model => Ok(()),
+        Ok(value) => Err(format!("the store has {:?}, the model {:?}", value, model)),
+        Err(e) => Err(format!("the store failed: {:?}", e)),
+    }
+}
+
+/// Runs `check_model` with random operations, each time on a new store of `open` as the
+/// openers of `conformance_tests!`, and shrinks the operations of a failure.
+#[cfg(test)]
+pub(crate) fn check_models<T, O, R>(open: O, capabilities: &Capabilities)
+where
+    T: for<'a> KeyValueStore<'a>,
+    O: Fn(&str) -> R,
+    R: Fn() -> T,
+{
+    use proptest::test_runner::{Config, TestCaseError, TestRunner};
+
+    // each case opens a store, so there are few of them unless PROPTEST_CASES is set
+    let mut config = Config {
+        failure_persistence: None,
+        ..Config::default()
+    };
+    if env::var_os("PROPTEST_CASES").is_none() {
+        config.cases = 32;
+    }
+    let mut runner = TestRunner::new(config);
+    let result = runner.run(&operations(capabilities), |operations| {
+        let dir = TempDir::new("model");
+        let open = open(dir.0.to_str().unwrap());
+        check_model(&open, capabilities, &operations).map_err(TestCaseError::fail)
+    });
+    if let Err(e) = result {
+        panic!("{}", e);
+    }
+}
+
+#[cfg(test)]
+fn operations(
+    capabilities: &Capabilities,
+) -> impl proptest::strategy::Strategy<Value = Vec<Operation>> {
+    use proptest::prelude::*;
+
+    // few short keys, so that the operations and the writes of a batch often hit the same ones
+    let min_len = if capabilities.empty_key { 0 } else { 1 };
+    let key = prop::collection::vec(0..3u8, min_len..3);
+    let value = prop::collection::vec(any::<u8>(), 0..3);
+    let write = prop_oneof![
+        4 => (key.clone(), value).prop_map(|(key, value)| Write::Put(key, value)),
+        2 => key.clone().prop_map(Write::Delete),
+        1 => (key.clone(), key.clone()).prop_map(|(start, end)| if start <= end {
+            Write::DeleteRange(start, end)
+        } else {
+            Write::DeleteRange(end, start)
+        }),
+    ];
+    let operation = prop_oneof![
+        2 => key.clone().prop_map(Operation::Get),
+        1 => key.clone().prop_map(Operation::Exists),
+        1 => (key, 0..4usize).prop_map(|(start, count)| Operation::Scan(start, count)),
+        4 => prop::collection::vec(write, 0..6).prop_map(Operation::Batch),
+        1 => Just(Operation::Reopen),
+    ];
+    prop::collection::vec(operation, 0..20)
+}
+
+// adds the `model` test of `check_models` to the tests of a store of the crate, with the
+// arguments of `conformance_tests!`, it isn't exported as proptest is a dev-dependency
+#[cfg(test)]
+macro_rules! model_tests {
+    ($store:ty) => {
+        crate::conformance::model_tests!($store, crate::conformance::Capabilities::default());
+    };
+    ($store:ty, $capabilities:expr) => {
+        crate::conformance::model_tests!(
+            $store,
+            crate::conformance::reopen::<$store>,
+            $capabilities
+        );
+    };
+    ($store:ty, $open:expr, $capabilities:expr) => {
+        #[test]
+        fn model() {
+            crate::conformance::check_models::<$store, _, _>($open, &$capabilities);
+        }
+    };
+}
+
+#[cfg(test)]
+pub(crate) use model_tests;
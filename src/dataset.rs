@@ -0,0 +1,243 @@
+use crate::value::ValueGenerator;
+use crate::workload::{BatchOperation, Task, TaskGenerator};
+use crate::Error;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+const MANIFEST: &str = "manifest.json";
+
+/// A database built once by a seeded workload, in `<datasets>/<name>/v<version>/<db_type>`,
+/// which is copied for every run so that the runs start from the same state.
+pub struct Dataset {
+    dir: PathBuf,
+}
+
+impl Dataset {
+    pub fn new(datasets: &str, name: &str, version: u32, db_type: &str) -> Self {
+        let dir = PathBuf::from(datasets)
+            .join(name)
+            .join(format!("v{}", version))
+            .join(db_type);
+        Dataset { dir }
+    }
+
+    pub fn db_path(&self) -> PathBuf {
+        self.dir.join("db")
+    }
+
+    // creates the empty database directory, a built version is never overwritten, but one
+    // without manifest, whose build failed, is built again
+    pub fn create(&self) -> Result<PathBuf, Error> {
+        if self.dir.join(MANIFEST).exists() {
+            return Err(Error::WorkloadError(format!(
+                "dataset {} already exists, build a new version instead",
+                self.dir.display()
+            )));
+        }
+        if self.dir.exists() {
+            fs::remove_dir_all(&self.dir)?;
+        }
+        fs::create_dir_all(self.db_path())?;
+        Ok(self.db_path())
+    }
+
+    // written last, a dataset without manifest was not completely built
+    pub fn write_manifest(&self, manifest: &Manifest) -> Result<(), Error> {
+        let file = File::create(self.dir.join(MANIFEST))?;
+        serde_json::to_writer_pretty(file, manifest)?;
+        Ok(())
+    }
+
+    pub fn manifest(&self) -> Result<Manifest, Error> {
+        let file = File::open(self.dir.join(MANIFEST)).map_err(|e| {
+            Error::WorkloadError(format!(
+                "dataset {} is not built: {}",
+                self.dir.display(),
+                e
+            ))
+        })?;
+        serde_json::from_reader(BufReader::new(file)).map_err(Into::into)
+    }
+
+    // a copy-on-write clone where the file system supports it, a full copy otherwise
+    pub fn copy_to(&self, path: &str) -> Result<(), Error> {
+        fs::create_dir_all(path)?;
+        if fs::read_dir(path)?.next().is_some() {
+            return Err(Error::WorkloadError(format!(
+                "{} is not empty, the dataset can't be copied to it",
+                path
+            )));
+        }
+        let cloned = Command::new("cp")
+            .arg("-R")
+            .arg("--reflink=auto")
+            .arg(self.db_path().join("."))
+            .arg(path)
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success());
+        // the cp of macOS and the BSDs has no --reflink
+        if !cloned {
+            fs::remove_dir_all(path)?;
+            copy_dir(&self.db_path(), Path::new(path))?;
+        }
+        Ok(())
+    }
+}
+
+// copies the files of `from` one by one into `to`, which is created
+fn copy_dir(from: &Path, to: &Path) -> Result<(), Error> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), target)?;
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    pub name: String,
+    pub version: u32,
+    pub db_type: String,
+    // the workload which built the dataset, regenerated to sample its keys
+    pub task_generator: TaskGenerator,
+    pub nums_task: usize,
+    pub seed: u64,
+    pub values: ValueGenerator,
+    // the `--options` of the store which built the dataset
+    #[serde(default)]
+    pub options: serde_json::Value,
+    #[serde(flatten)]
+    pub size: DatasetSize,
+}
+
+/// Puts of the workload which built a dataset, an overwritten or deleted key is still counted.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct DatasetSize {
+    pub keys: usize,
+    pub key_bytes: usize,
+    pub value_bytes: usize,
+}
+
+impl DatasetSize {
+    pub fn add(&mut self, task: &Task) {
+        if let Task::Batch(operations) = task {
+            for op in operations {
+                if let BatchOperation::Put(key, value_size) = op {
+                    self.keys += 1;
+                    self.key_bytes += key.len();
+                    self.value_bytes += value_size;
+                }
+            }
+        }
+    }
+}
+
+/// Bytes of the files under `path`, e.g. to measure how much a store grows during a run.
+pub fn disk_size(path: &Path) -> Result<u64, Error> {
+    let metadata = fs::metadata(path)?;
+    if !metadata.is_dir() {
+        return Ok(metadata.len());
+    }
+    let mut size = 0;
+    for entry in fs::read_dir(path)? {
+        size += disk_size(&entry?.path())?;
+    }
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workload::generate_workload;
+
+    #[test]
+    fn build_and_copy() {
+        let tmp_dir = tempfile::Builder::new()
+            .prefix("dataset")
+            .tempdir()
+            .unwrap();
+        let datasets = tmp_dir.path().join("datasets");
+        let dataset = Dataset::new(datasets.to_str().unwrap(), "blocks", 1, "lmdb");
+        let db_path = dataset.create().unwrap();
+        fs::write(db_path.join("data"), b"data").unwrap();
+        assert!(dataset.manifest().is_err());
+
+        let task_generator: TaskGenerator =
+            serde_json::from_str("{\"batch\":[{\"put\":[32,16]},{\"put\":[32,16]}]}").unwrap();
+        let mut size = DatasetSize::default();
+        generate_workload(&task_generator, 10, 7)
+            .unwrap()
+            .for_each(|task| size.add(&task));
+        let manifest = Manifest {
+            name: "blocks".to_string(),
+            version: 1,
+            db_type: "lmdb".to_string(),
+            task_generator,
+            nums_task: 10,
+            seed: 7,
+            values: ValueGenerator::default(),
+            options: serde_json::Value::Null,
+            size,
+        };
+        dataset.write_manifest(&manifest).unwrap();
+        let read = dataset.manifest().unwrap();
+        assert_eq!(
+            DatasetSize {
+                keys: 20,
+                key_bytes: 640,
+                value_bytes: 320
+            },
+            read.size
+        );
+        assert!(dataset.create().is_err());
+
+        let copy = tmp_dir.path().join("copy");
+        dataset.copy_to(copy.to_str().unwrap()).unwrap();
+        assert_eq!(b"data".to_vec(), fs::read(copy.join("data")).unwrap());
+        assert_eq!(4, disk_size(&copy).unwrap());
+        assert!(dataset.copy_to(copy.to_str().unwrap()).is_err());
+    }
+
+    #[test]
+    fn rebuild_without_manifest() {
+        let tmp_dir = tempfile::Builder::new()
+            .prefix("dataset")
+            .tempdir()
+            .unwrap();
+        let dataset = Dataset::new(tmp_dir.path().to_str().unwrap(), "blocks", 1, "lmdb");
+        let db_path = dataset.create().unwrap();
+        fs::write(db_path.join("data"), b"data").unwrap();
+        // the build failed before writing the manifest
+        let db_path = dataset.create().unwrap();
+        assert!(fs::read_dir(&db_path).unwrap().next().is_none());
+    }
+
+    #[test]
+    fn copy_dir_copies_every_file() {
+        let tmp_dir = tempfile::Builder::new()
+            .prefix("copy_dir")
+            .tempdir()
+            .unwrap();
+        let from = tmp_dir.path().join("from");
+        fs::create_dir_all(from.join("sub")).unwrap();
+        fs::write(from.join("data"), b"data").unwrap();
+        fs::write(from.join("sub").join("log"), b"log").unwrap();
+        let to = tmp_dir.path().join("to");
+        copy_dir(&from, &to).unwrap();
+        assert_eq!(b"data".to_vec(), fs::read(to.join("data")).unwrap());
+        assert_eq!(
+            b"log".to_vec(),
+            fs::read(to.join("sub").join("log")).unwrap()
+        );
+    }
+}
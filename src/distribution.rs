@@ -0,0 +1,275 @@
+use crate::Error;
+use rand::distributions::{Distribution, LogNormal, Normal, Pareto, WeightedIndex};
+use rand::Rng;
+use serde::de::{self, Deserializer, MapAccess, Visitor};
+use serde::ser::Serializer;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+/// Size of a generated key or value, either a constant or sampled from a distribution.
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum SizeGenerator {
+    Fixed(usize),
+    Distribution(SizeDistribution),
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SizeDistribution {
+    // min, max, both inclusive
+    Uniform(usize, usize),
+    // mean, std_dev
+    Normal(f64, f64),
+    // mean, std_dev of the size's natural logarithm
+    LogNormal(f64, f64),
+    // scale, shape
+    Pareto(f64, f64),
+    // path of a file with a `size weight` pair per line
+    Histogram(Histogram),
+}
+
+/// Largest size sampled from a distribution, the heavy tails of the log-normal and Pareto
+/// distributions are clamped to it instead of allocating values of any size.
+pub const MAX_SIZE: usize = 1 << 30;
+
+pub(crate) const DISTRIBUTIONS: &[&str] =
+    &["uniform", "normal", "log_normal", "pareto", "histogram"];
+
+impl SizeGenerator {
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> usize {
+        match self {
+            SizeGenerator::Fixed(size) => *size,
+            SizeGenerator::Distribution(distribution) => distribution.sample(rng),
+        }
+    }
+}
+
+impl SizeDistribution {
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> usize {
+        let size = match self {
+            SizeDistribution::Uniform(min, max) => return rng.gen_range(*min, *max + 1),
+            SizeDistribution::Normal(mean, std_dev) => Normal::new(*mean, *std_dev).sample(rng),
+            SizeDistribution::LogNormal(mean, std_dev) => {
+                LogNormal::new(*mean, *std_dev).sample(rng)
+            }
+            SizeDistribution::Pareto(scale, shape) => Pareto::new(*scale, *shape).sample(rng),
+            SizeDistribution::Histogram(histogram) => return histogram.sample(rng),
+        };
+        size.max(0.0).min(MAX_SIZE as f64).round() as usize
+    }
+}
+
+/// Empirical size distribution, serialized as the path of the file it was loaded from.
+#[derive(Debug)]
+pub struct Histogram {
+    path: String,
+    sizes: Vec<usize>,
+    index: WeightedIndex<f64>,
+}
+
+impl Histogram {
+    pub fn load(path: &str) -> Result<Self, Error> {
+        let mut sizes = Vec::new();
+        let mut weights = Vec::new();
+        for line in BufReader::new(File::open(path)?).lines() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut fields = line.split_whitespace();
+            let (size, weight) = match (fields.next(), fields.next(), fields.next()) {
+                (Some(size), Some(weight), None) => (size.parse().ok(), weight.parse().ok()),
+                _ => (None, None),
+            };
+            match (size, weight) {
+                (Some(size), Some(weight)) => {
+                    sizes.push(size);
+                    weights.push(weight);
+                }
+                _ => {
+                    return Err(Error::WorkloadError(format!(
+                        "invalid histogram line in {}: {}",
+                        path, line
+                    )))
+                }
+            }
+        }
+        let index = WeightedIndex::new(&weights).map_err(|e| {
+            Error::WorkloadError(format!("invalid histogram weights in {}: {:?}", path, e))
+        })?;
+        Ok(Histogram {
+            path: path.to_string(),
+            sizes,
+            index,
+        })
+    }
+
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> usize {
+        self.sizes[self.index.sample(rng)]
+    }
+}
+
+// hand written instead of `untagged`, which would hide the errors of the distribution
+impl<'de> Deserialize<'de> for SizeGenerator {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct SizeVisitor;
+
+        impl<'de> Visitor<'de> for SizeVisitor {
+            type Value = SizeGenerator;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a size or a size distribution")
+            }
+
+            fn visit_u64<E: de::Error>(self, size: u64) -> Result<Self::Value, E> {
+                Ok(SizeGenerator::Fixed(size as usize))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                let name: String = map
+                    .next_key()?
+                    .ok_or_else(|| de::Error::custom("missing size distribution"))?;
+                let distribution = next_distribution(&name, &mut map)?;
+                if map.next_key::<String>()?.is_some() {
+                    return Err(de::Error::custom("expected a single size distribution"));
+                }
+                Ok(SizeGenerator::Distribution(distribution))
+            }
+        }
+
+        deserializer.deserialize_any(SizeVisitor)
+    }
+}
+
+/// Reads the parameters of the distribution `name` from the next value of the map.
+pub(crate) fn next_distribution<'de, A: MapAccess<'de>>(
+    name: &str,
+    map: &mut A,
+) -> Result<SizeDistribution, A::Error> {
+    // the parameters which rand would panic on are rejected here
+    let distribution = match name {
+        "uniform" => {
+            let (min, max) = map.next_value()?;
+            if min > max || max > MAX_SIZE {
+                return Err(de::Error::custom(format!(
+                    "uniform needs min <= max <= {}",
+                    MAX_SIZE
+                )));
+            }
+            SizeDistribution::Uniform(min, max)
+        }
+        "normal" => {
+            let (mean, std_dev) = map.next_value()?;
+            check_mean_and_std_dev(name, mean, std_dev)?;
+            SizeDistribution::Normal(mean, std_dev)
+        }
+        "log_normal" => {
+            let (mean, std_dev) = map.next_value()?;
+            check_mean_and_std_dev(name, mean, std_dev)?;
+            SizeDistribution::LogNormal(mean, std_dev)
+        }
+        "pareto" => {
+            let (scale, shape): (f64, f64) = map.next_value()?;
+            if !(scale.is_finite() && scale > 0.0 && shape.is_finite() && shape > 0.0) {
+                return Err(de::Error::custom(
+                    "pareto needs a positive scale and a positive shape",
+                ));
+            }
+            SizeDistribution::Pareto(scale, shape)
+        }
+        "histogram" => SizeDistribution::Histogram(map.next_value()?),
+        _ => return Err(de::Error::unknown_variant(name, DISTRIBUTIONS)),
+    };
+    Ok(distribution)
+}
+
+fn check_mean_and_std_dev<E: de::Error>(name: &str, mean: f64, std_dev: f64) -> Result<(), E> {
+    if mean.is_finite() && std_dev.is_finite() && std_dev >= 0.0 {
+        Ok(())
+    } else {
+        Err(E::custom(format!(
+            "{} needs a finite mean and a non-negative std_dev",
+            name
+        )))
+    }
+}
+
+impl Serialize for Histogram {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.path)
+    }
+}
+
+impl<'de> Deserialize<'de> for Histogram {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let path = String::deserialize(deserializer)?;
+        Histogram::load(&path).map_err(|e| de::Error::custom(format!("{:?}", e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::thread_rng;
+    use std::io::Write;
+
+    #[test]
+    fn fixed_size_is_compatible() {
+        let size: SizeGenerator = serde_json::from_str("4096").unwrap();
+        assert_eq!(4096, size.sample(&mut thread_rng()));
+    }
+
+    #[test]
+    fn uniform_size_is_in_range() {
+        let size: SizeGenerator = serde_json::from_str("{\"uniform\":[10,20]}").unwrap();
+        let mut rng = thread_rng();
+        for _ in 0..1000 {
+            let s = size.sample(&mut rng);
+            assert!((10..=20).contains(&s));
+        }
+    }
+
+    #[test]
+    fn invalid_parameters() {
+        for json in &[
+            "{\"uniform\":[20,10]}",
+            "{\"uniform\":[0,18446744073709551615]}",
+            "{\"normal\":[100,-1]}",
+            "{\"log_normal\":[5,-0.5]}",
+            "{\"pareto\":[0,1]}",
+            "{\"pareto\":[100,-2]}",
+        ] {
+            assert!(
+                serde_json::from_str::<SizeGenerator>(json).is_err(),
+                "{}",
+                json
+            );
+        }
+    }
+
+    #[test]
+    fn heavy_tail_is_clamped() {
+        let size: SizeGenerator = serde_json::from_str("{\"pareto\":[1e300,0.01]}").unwrap();
+        let mut rng = thread_rng();
+        for _ in 0..100 {
+            assert!(size.sample(&mut rng) <= MAX_SIZE);
+        }
+    }
+
+    #[test]
+    fn histogram_from_file() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        writeln!(file, "# size weight\n100 1\n\n200 0").unwrap();
+        let json = serde_json::to_string(file.path().to_str().unwrap()).unwrap();
+        let size: SizeGenerator =
+            serde_json::from_str(&format!("{{\"histogram\":{}}}", json)).unwrap();
+        let mut rng = thread_rng();
+        for _ in 0..100 {
+            assert_eq!(100, size.sample(&mut rng));
+        }
+    }
+}
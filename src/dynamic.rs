@@ -0,0 +1,130 @@
+use crate::{Batch, Error, KeyValue, KeyValueStore, Snapshot};
+
+/// `KeyValueStore` as a trait object, for the stores which are only known at run time, e.g.
+/// the plugins. Every `KeyValueStore` is a `DynStore`.
+pub trait DynStore: Sync {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error>;
+    fn multi_get(&self, keys: &[&[u8]]) -> Result<Vec<Option<Vec<u8>>>, Error>;
+    fn exists(&self, key: &[u8]) -> Result<bool, Error>;
+    fn scan(&self, start: &[u8], count: usize) -> Result<Vec<KeyValue>, Error>;
+    fn batch(&self) -> Result<Box<dyn DynBatch + '_>, Error>;
+    fn bulk_load(&self, pairs: &mut dyn Iterator<Item = KeyValue>) -> Result<(), Error>;
+    fn snapshot(&self) -> Result<Box<dyn Snapshot + '_>, Error>;
+    fn compare_and_swap(
+        &self,
+        key: &[u8],
+        old: Option<&[u8]>,
+        new: Option<&[u8]>,
+    ) -> Result<bool, Error>;
+    fn add(&self, key: &[u8], delta: u64) -> Result<(), Error>;
+}
+
+/// `Batch` as a trait object, committed out of its box.
+pub trait DynBatch {
+    fn put(&mut self, key: &[u8], value: &[u8]) -> Result<(), Error>;
+    fn delete(&mut self, key: &[u8]) -> Result<(), Error>;
+    fn delete_range(&mut self, start: &[u8], end: &[u8]) -> Result<(), Error>;
+    fn commit(self: Box<Self>) -> Result<(), Error>;
+}
+
+impl<T> DynStore for T
+where
+    T: for<'a> KeyValueStore<'a> + Sync,
+{
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        KeyValueStore::get(self, key)
+    }
+
+    fn multi_get(&self, keys: &[&[u8]]) -> Result<Vec<Option<Vec<u8>>>, Error> {
+        KeyValueStore::multi_get(self, keys)
+    }
+
+    fn exists(&self, key: &[u8]) -> Result<bool, Error> {
+        KeyValueStore::exists(self, key)
+    }
+
+    fn scan(&self, start: &[u8], count: usize) -> Result<Vec<KeyValue>, Error> {
+        KeyValueStore::scan(self, start, count)
+    }
+
+    fn batch(&self) -> Result<Box<dyn DynBatch + '_>, Error> {
+        Ok(Box::new(KeyValueStore::batch(self)?))
+    }
+
+    fn bulk_load(&self, pairs: &mut dyn Iterator<Item = KeyValue>) -> Result<(), Error> {
+        KeyValueStore::bulk_load(self, pairs)
+    }
+
+    fn snapshot(&self) -> Result<Box<dyn Snapshot + '_>, Error> {
+        Ok(Box::new(KeyValueStore::snapshot(self)?))
+    }
+
+    fn compare_and_swap(
+        &self,
+        key: &[u8],
+        old: Option<&[u8]>,
+        new: Option<&[u8]>,
+    ) -> Result<bool, Error> {
+        KeyValueStore::compare_and_swap(self, key, old, new)
+    }
+
+    fn add(&self, key: &[u8], delta: u64) -> Result<(), Error> {
+        KeyValueStore::add(self, key, delta)
+    }
+}
+
+impl<B: Batch> DynBatch for B {
+    fn put(&mut self, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        Batch::put(self, key, value)
+    }
+
+    fn delete(&mut self, key: &[u8]) -> Result<(), Error> {
+        Batch::delete(self, key)
+    }
+
+    fn delete_range(&mut self, start: &[u8], end: &[u8]) -> Result<(), Error> {
+        Batch::delete_range(self, start, end)
+    }
+
+    fn commit(self: Box<Self>) -> Result<(), Error> {
+        Batch::commit(*self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::Store as Memory;
+
+    #[test]
+    fn dyn_store() {
+        let memory = Memory::new("");
+        let store: &dyn DynStore = &memory;
+        let mut batch = store.batch().unwrap();
+        batch.put(&[0, 0], &[0, 0, 0]).unwrap();
+        batch.put(&[1, 1], &[1, 1, 1]).unwrap();
+        batch.commit().unwrap();
+        store
+            .bulk_load(&mut (2..4u8).map(|i| (vec![i, i], vec![i])))
+            .unwrap();
+
+        let snapshot = store.snapshot().unwrap();
+        let mut batch = store.batch().unwrap();
+        batch.delete(&[0, 0]).unwrap();
+        batch.delete_range(&[2, 2], &[3, 3]).unwrap();
+        batch.commit().unwrap();
+
+        assert_eq!(None, store.get(&[0, 0]).unwrap());
+        assert!(snapshot.exists(&[0, 0]).unwrap());
+        assert_eq!(
+            vec![(vec![1, 1], vec![1, 1, 1]), (vec![3, 3], vec![3])],
+            store.scan(&[], 10).unwrap()
+        );
+        assert!(store.compare_and_swap(&[4], None, Some(&[4])).unwrap());
+        store.add(&[5], 5).unwrap();
+        assert_eq!(
+            vec![Some(vec![4]), Some(5u64.to_le_bytes().to_vec())],
+            store.multi_get(&[&[4], &[5]]).unwrap()
+        );
+    }
+}
@@ -0,0 +1,469 @@
+use crate::preset::{check_matched, Compression, LsmPreset, MEMTABLES};
+use crate::{
+    counter, parse_options, Batch, Error, KeyValue, KeyValueStore, Snapshot, Transaction,
+    TransactionalStore,
+};
+use fjall::{
+    AbstractTree, CompressionType, Config, PartitionCreateOptions, ReadTransaction, Slice,
+    TxKeyspace, TxPartitionHandle, WriteTransaction,
+};
+use std::convert::TryFrom;
+
+// the partition of the keys in the keyspace
+const PARTITION: &str = "kv";
+// the default level of zlib, which RocksDB uses too
+const ZLIB_LEVEL: u8 = 6;
+// bytes of keys and values put by each write transaction of a bulk load without ingestion
+const BULK_LOAD_TRANSACTION_SIZE: usize = 64 << 20;
+
+/// Fjall, an LSM tree in pure Rust, in one partition of a transactional keyspace. Its
+/// options are the LSM preset which configures `rocksdb::Store` the same way.
+pub struct Store {
+    keyspace: TxKeyspace,
+    partition: TxPartitionHandle,
+}
+
+// the preset in the options of fjall, the block size, the compression and the bloom filters
+// only apply when the partition is created
+fn apply_preset(
+    config: Config,
+    options: PartitionCreateOptions,
+    preset: &LsmPreset,
+) -> Result<(Config, PartitionCreateOptions), Error> {
+    let mut unmatched = Vec::new();
+    // fjall bounds the size of all the memtables instead of their number
+    let memtable_size = match u32::try_from(preset.memtable_size) {
+        Ok(size) if u64::from(size) * MEMTABLES >= 1 << 20 => size,
+        _ => {
+            unmatched.push(format!(
+                "memtable_size {}, fjall takes from 512 KiB to 4 GiB",
+                preset.memtable_size
+            ));
+            16 << 20
+        }
+    };
+    let compression = compression_type(preset.compression).unwrap_or_else(|| {
+        unmatched.push(format!(
+            "compression {:?}, fjall has none, lz4 and zlib",
+            preset.compression
+        ));
+        CompressionType::Lz4
+    });
+    let bloom_bits_per_key = match preset.bloom_bits_per_key {
+        Some(bits) if bits > 20 => {
+            unmatched.push(format!("bloom_bits_per_key {}, fjall takes up to 20", bits));
+            None
+        }
+        bits => bits,
+    };
+    let block_size = match u32::try_from(preset.block_size) {
+        Ok(size) if (1 << 10..=512 << 10).contains(&size) => size,
+        _ => {
+            unmatched.push(format!(
+                "block_size {}, fjall takes from 1 KiB to 512 KiB",
+                preset.block_size
+            ));
+            4 << 10
+        }
+    };
+    check_matched("fjall", unmatched)?;
+    Ok((
+        config.max_write_buffer_size(u64::from(memtable_size) * MEMTABLES),
+        options
+            .max_memtable_size(memtable_size)
+            .compression(compression)
+            .bloom_filter_bits(bloom_bits_per_key)
+            .block_size(block_size),
+    ))
+}
+
+fn compression_type(compression: Compression) -> Option<CompressionType> {
+    match compression {
+        Compression::None => Some(CompressionType::None),
+        Compression::Lz4 => Some(CompressionType::Lz4),
+        Compression::Zlib => Some(CompressionType::Miniz(ZLIB_LEVEL)),
+        Compression::Snappy | Compression::Zstd => None,
+    }
+}
+
+// an existing partition keeps the tables it was created with, e.g. in the copy of a
+// dataset, which must match the preset too. Its memtable size isn't exposed by fjall and
+// isn't checked
+fn check_partition(partition: &TxPartitionHandle, preset: &LsmPreset) -> Result<(), Error> {
+    let tree = partition.inner().tree.tree_config();
+    let mut unmatched = Vec::new();
+    if compression_type(preset.compression) != Some(tree.compression) {
+        unmatched.push(format!(
+            "compression {:?}, the partition has {:?}",
+            preset.compression, tree.compression
+        ));
+    }
+    if preset.bloom_bits_per_key.map_or(-1, |bits| bits as i8) != tree.bloom_bits_per_key {
+        unmatched.push(format!(
+            "bloom_bits_per_key {:?}, the partition has {}",
+            preset.bloom_bits_per_key, tree.bloom_bits_per_key
+        ));
+    }
+    if preset.block_size != u64::from(tree.data_block_size) {
+        unmatched.push(format!(
+            "block_size {}, the partition has {}",
+            preset.block_size, tree.data_block_size
+        ));
+    }
+    check_matched("fjall", unmatched)
+}
+
+impl<'a> KeyValueStore<'a> for Store {
+    type Batch = FjallBatch;
+    type Snapshot = FjallSnapshot;
+
+    fn new(path: &str) -> Self {
+        Self::open(path, &serde_json::Value::Null).expect("Failed to open fjall")
+    }
+
+    // the default options of fjall without options, the LSM preset otherwise
+    fn open(path: &str, options: &serde_json::Value) -> Result<Self, Error> {
+        let preset: Option<LsmPreset> = if options.is_null() {
+            None
+        } else {
+            Some(parse_options(options)?)
+        };
+        let (config, create_options) = match &preset {
+            Some(preset) => {
+                apply_preset(Config::new(path), PartitionCreateOptions::default(), preset)?
+            }
+            None => (Config::new(path), PartitionCreateOptions::default()),
+        };
+        let keyspace = config.open_transactional()?;
+        let existing = keyspace.partition_exists(PARTITION);
+        let partition = keyspace.open_partition(PARTITION, create_options)?;
+        if let (true, Some(preset)) = (existing, &preset) {
+            check_partition(&partition, preset)?;
+        }
+        Ok(Self {
+            keyspace,
+            partition,
+        })
+    }
+
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self.partition.get(key)?.map(|value| value.to_vec()))
+    }
+
+    // all the keys are read at the same instant
+    fn multi_get(&self, keys: &[&[u8]]) -> Result<Vec<Option<Vec<u8>>>, Error> {
+        let tx = self.keyspace.read_tx();
+        keys.iter()
+            .map(|key| get(&tx, &self.partition, key))
+            .collect()
+    }
+
+    fn exists(&self, key: &[u8]) -> Result<bool, Error> {
+        Ok(self.partition.contains_key(key)?)
+    }
+
+    fn scan(&self, start: &[u8], count: usize) -> Result<Vec<KeyValue>, Error> {
+        self.keyspace
+            .read_tx()
+            .range::<&[u8], _>(&self.partition, start..)
+            .take(count)
+            .map(|pair| {
+                let (key, value) = pair?;
+                Ok((key.to_vec(), value.to_vec()))
+            })
+            .collect()
+    }
+
+    fn batch(&self) -> Result<Self::Batch, Error> {
+        Ok(FjallBatch {
+            keyspace: self.keyspace.clone(),
+            partition: self.partition.clone(),
+            writes: Vec::new(),
+        })
+    }
+
+    // a new partition ingests the pairs in table files without the journal and the memtable,
+    // otherwise they're put by large write transactions
+    fn bulk_load<I: Iterator<Item = KeyValue>>(&self, pairs: I) -> Result<(), Error> {
+        // the tombstones are counted, so the partition is empty only when it's new
+        if self.partition.approximate_len() == 0 {
+            let mut last = None;
+            self.partition.inner().ingest(pairs.map(|(key, value)| {
+                let (key, value) = (Slice::from(key), Slice::from(value));
+                last = Some((key.clone(), value.clone()));
+                (key, value)
+            }))?;
+            // the ingested pairs have the sequence number 0, which the snapshots and the
+            // transactions only see after a write, so the last pair is put again
+            if let Some((key, value)) = last {
+                let mut tx = self.keyspace.write_tx();
+                tx.insert(&self.partition, key, value);
+                tx.commit()?;
+            }
+            return Ok(());
+        }
+        let mut pairs = pairs.peekable();
+        while pairs.peek().is_some() {
+            let mut tx = self.keyspace.write_tx();
+            let mut size = 0;
+            for (key, value) in pairs.by_ref() {
+                size += key.len() + value.len();
+                tx.insert(&self.partition, key, value);
+                if size >= BULK_LOAD_TRANSACTION_SIZE {
+                    break;
+                }
+            }
+            tx.commit()?;
+        }
+        Ok(())
+    }
+
+    // a read transaction is a snapshot of the keyspace
+    fn snapshot(&'a self) -> Result<Self::Snapshot, Error> {
+        Ok(FjallSnapshot {
+            tx: self.keyspace.read_tx(),
+            partition: self.partition.clone(),
+        })
+    }
+
+    fn compare_and_swap(
+        &self,
+        key: &[u8],
+        old: Option<&[u8]>,
+        new: Option<&[u8]>,
+    ) -> Result<bool, Error> {
+        let mut tx = self.keyspace.write_tx();
+        if tx.get(&self.partition, key)?.as_deref() != old {
+            return Ok(false);
+        }
+        match new {
+            Some(value) => tx.insert(&self.partition, key, value),
+            None => tx.remove(&self.partition, key),
+        }
+        tx.commit()?;
+        Ok(true)
+    }
+
+    fn add(&self, key: &[u8], delta: u64) -> Result<(), Error> {
+        let mut tx = self.keyspace.write_tx();
+        let sum = counter(tx.get(&self.partition, key)?.as_deref()).wrapping_add(delta);
+        tx.insert(&self.partition, key, sum.to_le_bytes());
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+impl<'a> TransactionalStore<'a> for Store {
+    type Transaction = FjallTransaction<'a>;
+
+    fn transaction(&'a self) -> Result<Self::Transaction, Error> {
+        Ok(FjallTransaction {
+            tx: self.keyspace.write_tx(),
+            partition: &self.partition,
+        })
+    }
+}
+
+fn get(
+    tx: &ReadTransaction,
+    partition: &TxPartitionHandle,
+    key: &[u8],
+) -> Result<Option<Vec<u8>>, Error> {
+    Ok(tx.get(partition, key)?.map(|value| value.to_vec()))
+}
+
+enum Write {
+    Put(Vec<u8>, Vec<u8>),
+    Delete(Vec<u8>),
+    DeleteRange(Vec<u8>, Vec<u8>),
+}
+
+// a write transaction holds the lock of the single writer, so the writes are buffered and
+// applied by one write transaction when committing
+pub struct FjallBatch {
+    keyspace: TxKeyspace,
+    partition: TxPartitionHandle,
+    writes: Vec<Write>,
+}
+
+impl Batch for FjallBatch {
+    fn put(&mut self, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        self.writes.push(Write::Put(key.to_vec(), value.to_vec()));
+        Ok(())
+    }
+
+    fn delete(&mut self, key: &[u8]) -> Result<(), Error> {
+        self.writes.push(Write::Delete(key.to_vec()));
+        Ok(())
+    }
+
+    fn delete_range(&mut self, start: &[u8], end: &[u8]) -> Result<(), Error> {
+        self.writes
+            .push(Write::DeleteRange(start.to_vec(), end.to_vec()));
+        Ok(())
+    }
+
+    fn commit(self) -> Result<(), Error> {
+        let mut tx = self.keyspace.write_tx();
+        for write in self.writes {
+            match write {
+                Write::Put(key, value) => tx.insert(&self.partition, key, value),
+                Write::Delete(key) => tx.remove(&self.partition, key),
+                // fjall has no range delete, the keys of the range are deleted one by one
+                Write::DeleteRange(start, end) => {
+                    let keys = tx
+                        .range::<&[u8], _>(&self.partition, &start[..]..&end[..])
+                        .map(|pair| pair.map(|(key, _)| key))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    for key in keys {
+                        tx.remove(&self.partition, key);
+                    }
+                }
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+pub struct FjallSnapshot {
+    tx: ReadTransaction,
+    partition: TxPartitionHandle,
+}
+
+impl Snapshot for FjallSnapshot {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        get(&self.tx, &self.partition, key)
+    }
+
+    fn multi_get(&self, keys: &[&[u8]]) -> Result<Vec<Option<Vec<u8>>>, Error> {
+        keys.iter()
+            .map(|key| get(&self.tx, &self.partition, key))
+            .collect()
+    }
+
+    fn exists(&self, key: &[u8]) -> Result<bool, Error> {
+        Ok(self.tx.contains_key(&self.partition, key)?)
+    }
+}
+
+pub struct FjallTransaction<'a> {
+    tx: WriteTransaction<'a>,
+    partition: &'a TxPartitionHandle,
+}
+
+impl<'a> Transaction for FjallTransaction<'a> {
+    fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self
+            .tx
+            .get(self.partition, key)?
+            .map(|value| value.to_vec()))
+    }
+
+    fn put(&mut self, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        self.tx.insert(self.partition, key, value);
+        Ok(())
+    }
+
+    fn delete(&mut self, key: &[u8]) -> Result<(), Error> {
+        self.tx.remove(self.partition, key);
+        Ok(())
+    }
+
+    fn commit(self) -> Result<(), Error> {
+        self.tx.commit()?;
+        Ok(())
+    }
+}
+
+impl From<fjall::Error> for Error {
+    fn from(e: fjall::Error) -> Error {
+        Error::DBError(e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conformance::Capabilities;
+    use tempfile;
+
+    // fjall has no empty key
+    fn capabilities() -> Capabilities {
+        Capabilities {
+            empty_key: false,
+            ..Capabilities::default()
+        }
+    }
+
+    crate::conformance_tests!(Store, capabilities());
+    crate::conformance::model_tests!(Store, capabilities());
+
+    #[test]
+    fn bulk_load_after_writes() {
+        let tmp_dir = tempfile::Builder::new()
+            .prefix("bulk_load_after_writes")
+            .tempdir()
+            .unwrap();
+        let store = Store::new(tmp_dir.path().to_str().unwrap());
+        let mut batch = store.batch().unwrap();
+        batch.put(&[0], &[0]).unwrap();
+        batch.commit().unwrap();
+        store
+            .bulk_load((1..5u8).map(|i| (vec![i], vec![i])))
+            .unwrap();
+
+        assert_eq!(
+            (0..5u8).map(|i| (vec![i], vec![i])).collect::<Vec<_>>(),
+            store.scan(&[], 10).unwrap()
+        );
+    }
+
+    #[test]
+    fn transaction() {
+        let tmp_dir = tempfile::Builder::new()
+            .prefix("transaction")
+            .tempdir()
+            .unwrap();
+        let store = Store::new(tmp_dir.path().to_str().unwrap());
+        let mut txn = store.transaction().unwrap();
+        txn.put(&[0, 0], &[0, 0, 0]).unwrap();
+        assert_eq!(Some(vec![0, 0, 0]), txn.get(&[0, 0]).unwrap());
+        txn.commit().unwrap();
+        assert_eq!(Some(vec![0, 0, 0]), store.get(&[0, 0]).unwrap());
+
+        // dropped without commit
+        let mut txn = store.transaction().unwrap();
+        txn.delete(&[0, 0]).unwrap();
+        drop(txn);
+        assert_eq!(Some(vec![0, 0, 0]), store.get(&[0, 0]).unwrap());
+    }
+
+    #[test]
+    fn preset() {
+        let tmp_dir = tempfile::Builder::new().prefix("preset").tempdir().unwrap();
+        let path = tmp_dir.path().to_str().unwrap();
+        assert!(Store::open(path, &serde_json::json!({"compression": "snappy"})).is_err());
+        assert!(Store::open(path, &serde_json::json!({"bloom_bits_per_key": 24})).is_err());
+        assert!(Store::open(path, &serde_json::json!({"block_size": 1 << 20})).is_err());
+        let options = serde_json::json!({
+            "memtable_size": 1 << 20,
+            "compression": "zlib",
+            "bloom_bits_per_key": null,
+            "block_size": 16384
+        });
+        let store = Store::open(path, &options).unwrap();
+        let mut batch = store.batch().unwrap();
+        batch.put(&[0, 0], &[0, 0, 0]).unwrap();
+        batch.commit().unwrap();
+        drop(store);
+
+        let store = Store::new(path);
+        assert_eq!(Some(vec![0, 0, 0]), store.get(&[0, 0]).unwrap());
+        drop(store);
+
+        // the partition keeps the zlib tables of 16 KiB it was created with
+        assert!(Store::open(path, &serde_json::json!({"block_size": 16384})).is_err());
+        assert!(Store::open(path, &options).is_ok());
+    }
+}
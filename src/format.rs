@@ -0,0 +1,482 @@
+use crate::workload::{BatchOperation, IncrementMethod, Task};
+use crate::Error;
+use serde::Deserialize;
+use std::convert::TryFrom;
+use std::io::{self, BufRead, Read, Write};
+use std::str::FromStr;
+
+const MAGIC: &[u8] = b"RKBW";
+const VERSION: u8 = 1;
+
+const TASK_GET: u8 = 0;
+const TASK_EXISTS: u8 = 1;
+const TASK_BATCH: u8 = 2;
+const TASK_VALUES: u8 = 3;
+const TASK_READ_MODIFY_WRITE: u8 = 4;
+const TASK_MULTI_GET: u8 = 5;
+const TASK_INCREMENT: u8 = 6;
+const TASK_SCAN: u8 = 7;
+
+const OPERATION_PUT: u8 = 0;
+const OPERATION_DELETE: u8 = 1;
+const OPERATION_DELETE_RANGE: u8 = 2;
+
+const INCREMENT_ADD: u8 = 0;
+const INCREMENT_COMPARE_AND_SWAP: u8 = 1;
+const INCREMENT_READ_MODIFY_WRITE: u8 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    // a json array of tasks, compatible with the original workload files
+    Json,
+    // magic header followed by length-prefixed tasks
+    Binary,
+}
+
+impl FromStr for Format {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Format::Json),
+            "binary" => Ok(Format::Binary),
+            _ => Err(Error::WorkloadError(format!("unknown format: {}", s))),
+        }
+    }
+}
+
+/// Streams tasks out of a workload, the format is detected from the first bytes.
+pub struct TaskReader<R> {
+    reader: R,
+    format: Format,
+    started: bool,
+    finished: bool,
+}
+
+impl<R: BufRead> TaskReader<R> {
+    pub fn new(mut reader: R) -> Result<Self, Error> {
+        let format = if reader.fill_buf()?.starts_with(MAGIC) {
+            reader.consume(MAGIC.len());
+            let version = read_u8(&mut reader)?;
+            if version != VERSION {
+                return Err(Error::WorkloadError(format!(
+                    "unsupported binary workload version: {}",
+                    version
+                )));
+            }
+            Format::Binary
+        } else {
+            if next_json_byte(&mut reader)? != Some(b'[') {
+                return Err(Error::WorkloadError(
+                    "workload is neither a json array nor a binary workload".to_string(),
+                ));
+            }
+            reader.consume(1);
+            Format::Json
+        };
+        Ok(Self {
+            reader,
+            format,
+            started: false,
+            finished: false,
+        })
+    }
+
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    fn read_json_task(&mut self) -> Result<Option<Task>, Error> {
+        match next_json_byte(&mut self.reader)? {
+            Some(b']') => {
+                self.reader.consume(1);
+                return Ok(None);
+            }
+            Some(b',') if self.started => self.reader.consume(1),
+            Some(_) if !self.started => {}
+            _ => {
+                return Err(Error::WorkloadError(
+                    "unexpected end of json workload".to_string(),
+                ))
+            }
+        }
+        self.started = true;
+        let mut deserializer = serde_json::Deserializer::from_reader(&mut self.reader);
+        Task::deserialize(&mut deserializer)
+            .map(Some)
+            .map_err(Into::into)
+    }
+
+    fn read_binary_task(&mut self) -> Result<Option<Task>, Error> {
+        if self.reader.fill_buf()?.is_empty() {
+            return Ok(None);
+        }
+        let task = match read_u8(&mut self.reader)? {
+            TASK_GET => Task::Get(read_bytes(&mut self.reader)?),
+            TASK_EXISTS => Task::Exists(read_bytes(&mut self.reader)?),
+            TASK_MULTI_GET => {
+                let len = read_u32(&mut self.reader)? as usize;
+                let mut keys = Vec::with_capacity(len);
+                for _ in 0..len {
+                    keys.push(read_bytes(&mut self.reader)?);
+                }
+                Task::MultiGet(keys)
+            }
+            TASK_SCAN => Task::Scan(
+                read_bytes(&mut self.reader)?,
+                read_u64(&mut self.reader)? as usize,
+            ),
+            TASK_BATCH => {
+                let len = read_u32(&mut self.reader)? as usize;
+                let mut operations = Vec::with_capacity(len);
+                for _ in 0..len {
+                    operations.push(match read_u8(&mut self.reader)? {
+                        OPERATION_PUT => BatchOperation::Put(
+                            read_bytes(&mut self.reader)?,
+                            read_u64(&mut self.reader)? as usize,
+                        ),
+                        OPERATION_DELETE => BatchOperation::Delete(read_bytes(&mut self.reader)?),
+                        OPERATION_DELETE_RANGE => BatchOperation::DeleteRange(
+                            read_bytes(&mut self.reader)?,
+                            read_bytes(&mut self.reader)?,
+                        ),
+                        tag => {
+                            return Err(Error::WorkloadError(format!(
+                                "unknown batch operation tag: {}",
+                                tag
+                            )))
+                        }
+                    });
+                }
+                Task::Batch(operations)
+            }
+            TASK_READ_MODIFY_WRITE => Task::ReadModifyWrite(
+                read_bytes(&mut self.reader)?,
+                read_u64(&mut self.reader)? as usize,
+            ),
+            TASK_INCREMENT => {
+                let key = read_bytes(&mut self.reader)?;
+                let method = match read_u8(&mut self.reader)? {
+                    INCREMENT_ADD => IncrementMethod::Add,
+                    INCREMENT_COMPARE_AND_SWAP => IncrementMethod::CompareAndSwap,
+                    INCREMENT_READ_MODIFY_WRITE => IncrementMethod::ReadModifyWrite,
+                    tag => {
+                        return Err(Error::WorkloadError(format!(
+                            "unknown increment method tag: {}",
+                            tag
+                        )))
+                    }
+                };
+                Task::Increment(key, method)
+            }
+            TASK_VALUES => Task::Values(serde_json::from_slice(&read_bytes(&mut self.reader)?)?),
+            tag => return Err(Error::WorkloadError(format!("unknown task tag: {}", tag))),
+        };
+        Ok(Some(task))
+    }
+}
+
+impl<R: BufRead> Iterator for TaskReader<R> {
+    type Item = Result<Task, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        let result = match self.format {
+            Format::Json => self.read_json_task(),
+            Format::Binary => self.read_binary_task(),
+        };
+        match result {
+            Ok(Some(task)) => Some(Ok(task)),
+            Ok(None) => {
+                self.finished = true;
+                None
+            }
+            Err(e) => {
+                self.finished = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// Streams tasks into a workload, `finish` must be called to terminate it.
+pub struct TaskWriter<W: Write> {
+    writer: W,
+    format: Format,
+    started: bool,
+}
+
+impl<W: Write> TaskWriter<W> {
+    pub fn new(mut writer: W, format: Format) -> Result<Self, Error> {
+        match format {
+            Format::Json => writer.write_all(b"[")?,
+            Format::Binary => {
+                writer.write_all(MAGIC)?;
+                writer.write_all(&[VERSION])?;
+            }
+        }
+        Ok(Self {
+            writer,
+            format,
+            started: false,
+        })
+    }
+
+    pub fn write(&mut self, task: &Task) -> Result<(), Error> {
+        match self.format {
+            Format::Json => {
+                if self.started {
+                    self.writer.write_all(b",")?;
+                }
+                serde_json::to_writer(&mut self.writer, task)?;
+            }
+            Format::Binary => self.write_binary_task(task)?,
+        }
+        self.started = true;
+        Ok(())
+    }
+
+    pub fn write_all<I: IntoIterator<Item = Task>>(&mut self, tasks: I) -> Result<(), Error> {
+        for task in tasks {
+            self.write(&task)?;
+        }
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<W, Error> {
+        if self.format == Format::Json {
+            self.writer.write_all(b"]")?;
+        }
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    fn write_binary_task(&mut self, task: &Task) -> Result<(), Error> {
+        let w = &mut self.writer;
+        match task {
+            Task::Get(key) => {
+                w.write_all(&[TASK_GET])?;
+                write_bytes(w, key)?;
+            }
+            Task::Exists(key) => {
+                w.write_all(&[TASK_EXISTS])?;
+                write_bytes(w, key)?;
+            }
+            Task::MultiGet(keys) => {
+                w.write_all(&[TASK_MULTI_GET])?;
+                write_len(w, keys.len())?;
+                for key in keys {
+                    write_bytes(w, key)?;
+                }
+            }
+            Task::Scan(start, count) => {
+                w.write_all(&[TASK_SCAN])?;
+                write_bytes(w, start)?;
+                w.write_all(&(*count as u64).to_le_bytes())?;
+            }
+            Task::Batch(operations) => {
+                w.write_all(&[TASK_BATCH])?;
+                write_len(w, operations.len())?;
+                for op in operations {
+                    match op {
+                        BatchOperation::Put(key, value_size) => {
+                            w.write_all(&[OPERATION_PUT])?;
+                            write_bytes(w, key)?;
+                            w.write_all(&(*value_size as u64).to_le_bytes())?;
+                        }
+                        BatchOperation::Delete(key) => {
+                            w.write_all(&[OPERATION_DELETE])?;
+                            write_bytes(w, key)?;
+                        }
+                        BatchOperation::DeleteRange(start, end) => {
+                            w.write_all(&[OPERATION_DELETE_RANGE])?;
+                            write_bytes(w, start)?;
+                            write_bytes(w, end)?;
+                        }
+                    }
+                }
+            }
+            Task::ReadModifyWrite(key, value_size) => {
+                w.write_all(&[TASK_READ_MODIFY_WRITE])?;
+                write_bytes(w, key)?;
+                w.write_all(&(*value_size as u64).to_le_bytes())?;
+            }
+            Task::Increment(key, method) => {
+                w.write_all(&[TASK_INCREMENT])?;
+                write_bytes(w, key)?;
+                w.write_all(&[match method {
+                    IncrementMethod::Add => INCREMENT_ADD,
+                    IncrementMethod::CompareAndSwap => INCREMENT_COMPARE_AND_SWAP,
+                    IncrementMethod::ReadModifyWrite => INCREMENT_READ_MODIFY_WRITE,
+                }])?;
+            }
+            // rare enough to be encoded as json
+            Task::Values(values) => {
+                w.write_all(&[TASK_VALUES])?;
+                write_bytes(w, &serde_json::to_vec(values)?)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn next_json_byte<R: BufRead>(reader: &mut R) -> io::Result<Option<u8>> {
+    loop {
+        let (skip, byte) = {
+            let buf = reader.fill_buf()?;
+            if buf.is_empty() {
+                return Ok(None);
+            }
+            match buf.iter().position(|b| !b.is_ascii_whitespace()) {
+                Some(pos) => (pos, Some(buf[pos])),
+                None => (buf.len(), None),
+            }
+        };
+        reader.consume(skip);
+        if byte.is_some() {
+            return Ok(byte);
+        }
+    }
+}
+
+pub(crate) fn write_bytes<W: Write>(w: &mut W, bytes: &[u8]) -> io::Result<()> {
+    write_len(w, bytes.len())?;
+    w.write_all(bytes)
+}
+
+// the lengths are u32, a longer one fails instead of being truncated
+fn write_len<W: Write>(w: &mut W, len: usize) -> io::Result<()> {
+    let len = u32::try_from(len).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("the length {} doesn't fit in a u32", len),
+        )
+    })?;
+    w.write_all(&len.to_le_bytes())
+}
+
+pub(crate) fn read_u8<R: BufRead>(reader: &mut R) -> io::Result<u8> {
+    let mut buf = [0u8; 1];
+    reader.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+pub(crate) fn read_u32<R: BufRead>(reader: &mut R) -> io::Result<u32> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+pub(crate) fn read_u64<R: BufRead>(reader: &mut R) -> io::Result<u64> {
+    let mut buf = [0u8; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+// the length comes from the input, so the buffer grows with the bytes actually read
+pub(crate) fn read_bytes<R: BufRead>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let len = read_u32(reader)?;
+    let mut buf = Vec::new();
+    reader.by_ref().take(len.into()).read_to_end(&mut buf)?;
+    if buf.len() != len as usize {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!("{} of {} bytes before the end", buf.len(), len),
+        ));
+    }
+    Ok(buf)
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::IOError(e.to_string())
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Error {
+        Error::WorkloadError(e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::value::ValueGenerator;
+
+    fn tasks() -> Vec<Task> {
+        vec![
+            Task::Values(ValueGenerator::CompressionRatio(0.5)),
+            Task::Get(vec![0, 1]),
+            Task::Exists(vec![]),
+            Task::MultiGet(vec![vec![7], vec![], vec![8, 9]]),
+            Task::Scan(vec![12], 100),
+            Task::ReadModifyWrite(vec![6], 128),
+            Task::Increment(vec![10], IncrementMethod::Add),
+            Task::Increment(vec![11], IncrementMethod::CompareAndSwap),
+            Task::Batch(vec![
+                BatchOperation::Put(vec![2, 3, 4], 4096),
+                BatchOperation::Delete(vec![5]),
+                BatchOperation::DeleteRange(vec![6], vec![9, 9]),
+            ]),
+        ]
+    }
+
+    fn roundtrip(format: Format) {
+        let mut writer = TaskWriter::new(Vec::new(), format).unwrap();
+        writer.write_all(tasks()).unwrap();
+        let buf = writer.finish().unwrap();
+
+        let reader = TaskReader::new(&buf[..]).unwrap();
+        assert_eq!(format, reader.format());
+        let decoded = reader.collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(tasks(), decoded);
+    }
+
+    #[test]
+    fn json_roundtrip() {
+        roundtrip(Format::Json);
+    }
+
+    #[test]
+    fn binary_roundtrip() {
+        roundtrip(Format::Binary);
+    }
+
+    #[test]
+    fn read_legacy_json() {
+        let json = b" [ {\"get\":[0,1]} ,\n{\"batch\":[{\"put\":[[2],16]}]} ] ";
+        let decoded = TaskReader::new(&json[..])
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(
+            vec![
+                Task::Get(vec![0, 1]),
+                Task::Batch(vec![BatchOperation::Put(vec![2], 16)])
+            ],
+            decoded
+        );
+    }
+
+    #[test]
+    fn empty_workload() {
+        for format in &[Format::Json, Format::Binary] {
+            let buf = TaskWriter::new(Vec::new(), *format)
+                .unwrap()
+                .finish()
+                .unwrap();
+            assert_eq!(0, TaskReader::new(&buf[..]).unwrap().count());
+        }
+    }
+
+    #[test]
+    fn lengths() {
+        // a length of 4 GiB with two bytes fails without allocating it
+        assert!(read_bytes(&mut &[255, 255, 255, 255, 1, 2][..]).is_err());
+        assert_eq!(vec![1], read_bytes(&mut &[1, 0, 0, 0, 1, 2][..]).unwrap());
+        assert!(write_len(&mut Vec::new(), u32::MAX as usize + 1).is_err());
+    }
+}
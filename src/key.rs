@@ -0,0 +1,304 @@
+use crate::distribution::{next_distribution, SizeGenerator, DISTRIBUTIONS};
+use rand::distributions::Standard;
+use rand::Rng;
+use serde::de::value::MapAccessDeserializer;
+use serde::de::{self, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::Serializer;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+const KEY_GENERATORS: &[&str] = &["random", "sequential", "reverse_sequential", "composite"];
+
+/// Generates the keys of a workload, every key has a sequence number which increases
+/// with the task index and the position of the operation in the task.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyGenerator {
+    // key_size
+    Random(SizeGenerator),
+    // key_size, the big-endian sequence number
+    Sequential(usize),
+    // key_size, the big-endian sequence number counted down from `u64::MAX`
+    ReverseSequential(usize),
+    Composite(Vec<KeySegment>),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeySegment {
+    Fixed(Vec<u8>),
+    Sequential(usize),
+    ReverseSequential(usize),
+    Random(SizeGenerator),
+}
+
+impl KeyGenerator {
+    pub fn generate<R: Rng>(&self, rng: &mut R, seq: u64) -> Vec<u8> {
+        match self {
+            KeyGenerator::Random(size) => rand_key(rng, size),
+            KeyGenerator::Sequential(size) => seq_key(seq, *size),
+            KeyGenerator::ReverseSequential(size) => seq_key(u64::MAX - seq, *size),
+            KeyGenerator::Composite(segments) => {
+                let mut key = Vec::new();
+                for segment in segments {
+                    match segment {
+                        KeySegment::Fixed(bytes) => key.extend_from_slice(bytes),
+                        KeySegment::Sequential(size) => key.extend(seq_key(seq, *size)),
+                        KeySegment::ReverseSequential(size) => {
+                            key.extend(seq_key(u64::MAX - seq, *size))
+                        }
+                        KeySegment::Random(size) => key.extend(rand_key(rng, size)),
+                    }
+                }
+                key
+            }
+        }
+    }
+
+    // the smallest key of the sequence number whatever its random segments, which end the bound
+    pub fn lower_bound(&self, seq: u64) -> Vec<u8> {
+        match self {
+            KeyGenerator::Random(_) => Vec::new(),
+            KeyGenerator::Sequential(size) => seq_key(seq, *size),
+            KeyGenerator::ReverseSequential(size) => seq_key(u64::MAX - seq, *size),
+            KeyGenerator::Composite(segments) => {
+                let mut key = Vec::new();
+                for segment in segments {
+                    match segment {
+                        KeySegment::Fixed(bytes) => key.extend_from_slice(bytes),
+                        KeySegment::Sequential(size) => key.extend(seq_key(seq, *size)),
+                        KeySegment::ReverseSequential(size) => {
+                            key.extend(seq_key(u64::MAX - seq, *size))
+                        }
+                        KeySegment::Random(_) => break,
+                    }
+                }
+                key
+            }
+        }
+    }
+
+    // the range of the keys of the sequence numbers `0..count`, None when the keys aren't
+    // ordered by a sequence number before their random segments, or the range has no end
+    pub fn first_range(&self, count: u64) -> Option<(Vec<u8>, Vec<u8>)> {
+        let (reverse, random) = match self {
+            KeyGenerator::Random(_) => return None,
+            KeyGenerator::Sequential(_) => (false, false),
+            KeyGenerator::ReverseSequential(_) => (true, false),
+            KeyGenerator::Composite(segments) => {
+                let is_random = |segment: &&KeySegment| matches!(segment, KeySegment::Random(_));
+                let reverse = segments
+                    .iter()
+                    .take_while(|segment| !is_random(segment))
+                    .find_map(|segment| match segment {
+                        KeySegment::Sequential(_) => Some(false),
+                        KeySegment::ReverseSequential(_) => Some(true),
+                        _ => None,
+                    })?;
+                (reverse, segments.iter().any(|segment| is_random(&segment)))
+            }
+        };
+        if !reverse {
+            return Some((self.lower_bound(0), self.lower_bound(count)));
+        }
+        // the keys of a reverse sequence decrease, the range ends after the keys of 0
+        let last = self.lower_bound(0);
+        let end = if random {
+            prefix_end(last)?
+        } else {
+            let mut end = last;
+            end.push(0);
+            end
+        };
+        match count {
+            0 => Some((end.clone(), end)),
+            _ => Some((self.lower_bound(count - 1), end)),
+        }
+    }
+}
+
+// the smallest key after the keys which start with `prefix`, None when they're all 0xff
+fn prefix_end(mut prefix: Vec<u8>) -> Option<Vec<u8>> {
+    while let Some(byte) = prefix.pop() {
+        if byte < u8::MAX {
+            prefix.push(byte + 1);
+            return Some(prefix);
+        }
+    }
+    None
+}
+
+fn rand_key<R: Rng>(rng: &mut R, size: &SizeGenerator) -> Vec<u8> {
+    let len = size.sample(rng);
+    rng.sample_iter(&Standard).take(len).collect()
+}
+
+// keys shorter than 8 bytes keep the low bytes of the sequence number and wrap around
+fn seq_key(seq: u64, size: usize) -> Vec<u8> {
+    let bytes = seq.to_be_bytes();
+    if size >= bytes.len() {
+        let mut key = vec![0; size - bytes.len()];
+        key.extend_from_slice(&bytes);
+        key
+    } else {
+        bytes[bytes.len() - size..].to_vec()
+    }
+}
+
+// a bare size or size distribution is a random key, as in the original generators
+impl<'de> Deserialize<'de> for KeyGenerator {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct KeyVisitor;
+
+        impl<'de> Visitor<'de> for KeyVisitor {
+            type Value = KeyGenerator;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a key size or a key generator")
+            }
+
+            fn visit_u64<E: de::Error>(self, size: u64) -> Result<Self::Value, E> {
+                Ok(KeyGenerator::Random(SizeGenerator::Fixed(size as usize)))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                let name: String = map
+                    .next_key()?
+                    .ok_or_else(|| de::Error::custom("missing key generator"))?;
+                let generator = match name.as_str() {
+                    "random" => KeyGenerator::Random(map.next_value()?),
+                    "sequential" => KeyGenerator::Sequential(map.next_value()?),
+                    "reverse_sequential" => KeyGenerator::ReverseSequential(map.next_value()?),
+                    "composite" => KeyGenerator::Composite(map.next_value()?),
+                    _ if DISTRIBUTIONS.contains(&name.as_str()) => KeyGenerator::Random(
+                        SizeGenerator::Distribution(next_distribution(&name, &mut map)?),
+                    ),
+                    _ => return Err(de::Error::unknown_variant(&name, KEY_GENERATORS)),
+                };
+                if map.next_key::<String>()?.is_some() {
+                    return Err(de::Error::custom("expected a single key generator"));
+                }
+                Ok(generator)
+            }
+        }
+
+        deserializer.deserialize_any(KeyVisitor)
+    }
+}
+
+/// Key of a read task, `miss_ratio` of the reads look up keys which were never put.
+#[derive(Debug)]
+pub struct ReadGenerator {
+    pub key: KeyGenerator,
+    pub miss_ratio: f64,
+}
+
+impl ReadGenerator {
+    // whether the next read should miss
+    pub fn miss<R: Rng>(&self, rng: &mut R) -> bool {
+        self.miss_ratio > 0.0 && rng.gen_bool(self.miss_ratio)
+    }
+}
+
+impl Serialize for ReadGenerator {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        (&self.key, self.miss_ratio).serialize(serializer)
+    }
+}
+
+// either a key generator, or a `[key generator, miss_ratio]` pair
+impl<'de> Deserialize<'de> for ReadGenerator {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ReadVisitor;
+
+        impl<'de> Visitor<'de> for ReadVisitor {
+            type Value = ReadGenerator;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a key generator or a [key generator, miss_ratio] pair")
+            }
+
+            fn visit_u64<E: de::Error>(self, size: u64) -> Result<Self::Value, E> {
+                Ok(ReadGenerator {
+                    key: KeyGenerator::Random(SizeGenerator::Fixed(size as usize)),
+                    miss_ratio: 0.0,
+                })
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+                Ok(ReadGenerator {
+                    key: KeyGenerator::deserialize(MapAccessDeserializer::new(map))?,
+                    miss_ratio: 0.0,
+                })
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                let key = seq
+                    .next_element()?
+                    .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+                let miss_ratio: f64 = seq
+                    .next_element()?
+                    .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+                if !(0.0..=1.0).contains(&miss_ratio) {
+                    return Err(de::Error::custom("miss_ratio must be between 0 and 1"));
+                }
+                Ok(ReadGenerator { key, miss_ratio })
+            }
+        }
+
+        deserializer.deserialize_any(ReadVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::thread_rng;
+
+    #[test]
+    fn sequential_keys_are_ordered() {
+        let mut rng = thread_rng();
+        let keys: KeyGenerator = serde_json::from_str("{\"sequential\":10}").unwrap();
+        let reverse_keys: KeyGenerator =
+            serde_json::from_str("{\"reverse_sequential\":10}").unwrap();
+        for seq in 0..1000 {
+            assert_eq!(10, keys.generate(&mut rng, seq).len());
+            assert!(keys.generate(&mut rng, seq) < keys.generate(&mut rng, seq + 1));
+            assert!(
+                reverse_keys.generate(&mut rng, seq) > reverse_keys.generate(&mut rng, seq + 1)
+            );
+        }
+    }
+
+    #[test]
+    fn composite_key() {
+        let keys: KeyGenerator = serde_json::from_str(
+            "{\"composite\":[{\"fixed\":[7]},{\"sequential\":8},{\"random\":32}]}",
+        )
+        .unwrap();
+        let key = keys.generate(&mut thread_rng(), 258);
+        assert_eq!(41, key.len());
+        assert_eq!(&[7, 0, 0, 0, 0, 0, 0, 1, 2], &key[..9]);
+        assert_eq!(&key[..9], &keys.lower_bound(258)[..]);
+        assert!(keys.lower_bound(259) > key);
+    }
+
+    #[test]
+    fn size_is_random_key() {
+        let mut rng = thread_rng();
+        let keys: KeyGenerator = serde_json::from_str("32").unwrap();
+        assert_eq!(32, keys.generate(&mut rng, 0).len());
+        let keys: KeyGenerator = serde_json::from_str("{\"uniform\":[1,2]}").unwrap();
+        assert!(keys.generate(&mut rng, 0).len() <= 2);
+    }
+
+    #[test]
+    fn read_generator() {
+        let read: ReadGenerator = serde_json::from_str("{\"sequential\":8}").unwrap();
+        assert!(read.miss_ratio.abs() < f64::EPSILON);
+        let read: ReadGenerator = serde_json::from_str("[32,0.25]").unwrap();
+        assert!((read.miss_ratio - 0.25).abs() < f64::EPSILON);
+        assert_eq!(32, read.key.generate(&mut thread_rng(), 0).len());
+        assert!(serde_json::from_str::<ReadGenerator>("[32,1.5]").is_err());
+    }
+}
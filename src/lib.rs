@@ -1,23 +1,141 @@
+#[cfg(any(feature = "parity_db", feature = "rocksdb", feature = "sled"))]
+pub mod buffered;
+pub mod conformance;
+pub mod dataset;
+pub mod distribution;
+pub mod dynamic;
+#[cfg(feature = "fjall")]
+pub mod fjall;
+pub mod format;
+pub mod key;
+#[cfg(feature = "lmdb")]
 pub mod lmdb_zero;
+#[cfg(feature = "mdbx")]
+pub mod mdbx;
+pub mod memory;
+#[cfg(feature = "parity_db")]
+pub mod parity_db;
+pub mod plugin;
+#[cfg(any(feature = "rocksdb", feature = "fjall"))]
+pub mod preset;
+#[cfg(feature = "redb")]
+pub mod redb;
+pub mod registry;
+pub mod remote;
+#[cfg(feature = "rocksdb")]
 pub mod rocksdb;
+#[cfg(feature = "sled")]
 pub mod sled;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod value;
 pub mod workload;
 
+use serde::de::DeserializeOwned;
+
 #[derive(Debug)]
 pub enum Error {
     DBError(String),
+    IOError(String),
+    WorkloadError(String),
+    // a transaction was aborted by a concurrent one
+    ConflictError(String),
 }
 
+// a key and its value, as returned by a scan
+pub type KeyValue = (Vec<u8>, Vec<u8>);
+
 pub trait KeyValueStore<'a> {
-    type Batch: Batch;
+    type Batch: Batch + 'a;
+    type Snapshot: Snapshot + 'a;
     fn new(path: &str) -> Self;
+    // opens the store with its options, a json object given by `--options`, the stores
+    // without options only take null
+    fn open(path: &str, options: &serde_json::Value) -> Result<Self, Error>
+    where
+        Self: Sized,
+    {
+        if options.is_null() {
+            Ok(Self::new(path))
+        } else {
+            Err(Error::WorkloadError(format!(
+                "the store has no options: {}",
+                options
+            )))
+        }
+    }
     fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error>;
+    fn multi_get(&self, keys: &[&[u8]]) -> Result<Vec<Option<Vec<u8>>>, Error>;
     fn exists(&self, key: &[u8]) -> Result<bool, Error>;
+    // the first `count` pairs from `start` on, in key order
+    fn scan(&self, start: &[u8], count: usize) -> Result<Vec<KeyValue>, Error>;
     fn batch(&self) -> Result<Self::Batch, Error>;
+    // puts pairs in increasing key order which all come after the keys of the store,
+    // faster than batches when filling a new store
+    fn bulk_load<I: Iterator<Item = KeyValue>>(&self, pairs: I) -> Result<(), Error>;
+    fn snapshot(&'a self) -> Result<Self::Snapshot, Error>;
+    // puts `new`, or deletes the key when it's None, only if the current value is `old`,
+    // returns whether the value was swapped. The stores without a native swap check the
+    // value in one write transaction, which their single writer makes atomic
+    fn compare_and_swap(
+        &self,
+        key: &[u8],
+        old: Option<&[u8]>,
+        new: Option<&[u8]>,
+    ) -> Result<bool, Error>;
+    // adds `delta` to the counter at `key`, without reading it when the engine can merge,
+    // otherwise the counter is read and written in one write transaction
+    fn add(&self, key: &[u8], delta: u64) -> Result<(), Error>;
+}
+
+/// Parses the options of a store given to `KeyValueStore::open`, null for the defaults.
+pub fn parse_options<O: DeserializeOwned + Default>(
+    options: &serde_json::Value,
+) -> Result<O, Error> {
+    if options.is_null() {
+        Ok(O::default())
+    } else {
+        O::deserialize(options).map_err(|e| Error::WorkloadError(format!("invalid options: {}", e)))
+    }
+}
+
+/// Value of a counter updated by `add`, a little-endian u64 which is 0 when missing.
+pub fn counter(value: Option<&[u8]>) -> u64 {
+    let mut bytes = [0; 8];
+    if let Some(value) = value {
+        let len = value.len().min(8);
+        bytes[..len].copy_from_slice(&value[..len]);
+    }
+    u64::from_le_bytes(bytes)
+}
+
+/// Reads the store as it was when the snapshot was taken, while writes continue.
+pub trait Snapshot {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error>;
+    fn multi_get(&self, keys: &[&[u8]]) -> Result<Vec<Option<Vec<u8>>>, Error>;
+    fn exists(&self, key: &[u8]) -> Result<bool, Error>;
+}
+
+/// Stores which can read and write in one transaction, a transaction which conflicts with
+/// a concurrent one fails with `Error::ConflictError` and has no effect. The transactions
+/// of the stores with a single writer never conflict, they wait for each other.
+pub trait TransactionalStore<'a>: KeyValueStore<'a> {
+    type Transaction: Transaction;
+    fn transaction(&'a self) -> Result<Self::Transaction, Error>;
+}
+
+pub trait Transaction {
+    // sees the puts and deletes of the transaction
+    fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>, Error>;
+    fn put(&mut self, key: &[u8], value: &[u8]) -> Result<(), Error>;
+    fn delete(&mut self, key: &[u8]) -> Result<(), Error>;
+    fn commit(self) -> Result<(), Error>;
 }
 
 pub trait Batch {
     fn put(&mut self, key: &[u8], value: &[u8]) -> Result<(), Error>;
     fn delete(&mut self, key: &[u8]) -> Result<(), Error>;
+    // deletes the keys from `start` included to `end` excluded
+    fn delete_range(&mut self, start: &[u8], end: &[u8]) -> Result<(), Error>;
     fn commit(self) -> Result<(), Error>;
 }
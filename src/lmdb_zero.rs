@@ -1,10 +1,26 @@
-use crate::{Batch, Error, KeyValueStore};
+use crate::{
+    counter, parse_options, Batch, Error, KeyValue, KeyValueStore, Snapshot, Transaction,
+    TransactionalStore,
+};
 use lmdb_zero::{
-    self, Database, DatabaseOptions, EnvBuilder, Environment, Ignore, LmdbResultExt,
+    self, ConstAccessor, Database, DatabaseOptions, EnvBuilder, Environment, Ignore, LmdbResultExt,
     ReadTransaction, WriteTransaction,
 };
+use serde::Deserialize;
 use std::sync::Arc;
 
+// bytes of keys and values put by each write transaction of a bulk load
+const BULK_LOAD_TRANSACTION_SIZE: usize = 64 << 20;
+
+/// Options of `--options`, e.g. `{"notls":true}`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LmdbOptions {
+    // lets a thread which holds a snapshot open other read transactions, off by default as
+    // the readers of `run` hold their snapshots in their own threads
+    notls: bool,
+}
+
 pub struct Store {
     env: Arc<Environment>,
     db: Arc<Database<'static>>,
@@ -12,51 +28,71 @@ pub struct Store {
 
 impl<'a> KeyValueStore<'a> for Store {
     type Batch = LmdbBatch<'a>;
+    type Snapshot = LmdbSnapshot<'a>;
 
     fn new(path: &str) -> Self {
-        let mut env_builder = EnvBuilder::new().unwrap();
-        env_builder.set_maxdbs(1).unwrap();
+        Self::open(path, &serde_json::Value::Null).expect("Failed to open LMDB")
+    }
+
+    fn open(path: &str, options: &serde_json::Value) -> Result<Self, Error> {
+        let options: LmdbOptions = parse_options(options)?;
+        let mut env_builder = EnvBuilder::new()?;
+        env_builder.set_maxdbs(1)?;
         // max 1TB
-        env_builder
-            .set_mapsize(1_099_511_627_776)
-            .unwrap_or_else(|e| {
-                panic!("Unable to allocate LMDB space: {:?}", e);
-            });
+        env_builder.set_mapsize(1_099_511_627_776)?;
         // By default, each write to rocksdb is asynchronous: it returns after pushing the write from the process into the operating system
         // Using NOSYNC here to keep same behaviour with rocksdb.
-        let env = unsafe {
-            Arc::new(
-                env_builder
-                    .open(&path, lmdb_zero::open::NOSYNC, 0o600)
-                    .unwrap(),
-            )
-        };
-        let db = Arc::new(
-            Database::open(
-                Arc::clone(&env),
-                Some("lmdb"),
-                &DatabaseOptions::new(lmdb_zero::db::CREATE),
-            )
-            .unwrap(),
-        );
-        Self { env, db }
+        let mut flags = lmdb_zero::open::NOSYNC;
+        if options.notls {
+            flags |= lmdb_zero::open::NOTLS;
+        }
+        let env = unsafe { Arc::new(env_builder.open(path, flags, 0o600)?) };
+        let db = Arc::new(Database::open(
+            Arc::clone(&env),
+            Some("lmdb"),
+            &DatabaseOptions::new(lmdb_zero::db::CREATE),
+        )?);
+        Ok(Self { env, db })
     }
 
     fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
         let tx = ReadTransaction::new(Arc::clone(&self.env))?;
         let access = tx.access();
-        access
-            .get(&self.db, key)
-            .map(|res: &[u8]| res.to_vec())
-            .to_opt()
-            .map_err(Into::into)
+        get(&access, &self.db, key)
+    }
+
+    // all the keys are read by one transaction instead of one per key
+    fn multi_get(&self, keys: &[&[u8]]) -> Result<Vec<Option<Vec<u8>>>, Error> {
+        let tx = ReadTransaction::new(Arc::clone(&self.env))?;
+        let access = tx.access();
+        keys.iter().map(|key| get(&access, &self.db, key)).collect()
     }
 
     fn exists(&self, key: &[u8]) -> Result<bool, Error> {
         let tx = ReadTransaction::new(Arc::clone(&self.env))?;
         let access = tx.access();
-        let result: lmdb_zero::error::Result<&Ignore> = access.get(&self.db, key);
-        result.to_opt().map(|r| r.is_some()).map_err(Into::into)
+        exists(&access, &self.db, key)
+    }
+
+    fn scan(&self, start: &[u8], count: usize) -> Result<Vec<KeyValue>, Error> {
+        let tx = ReadTransaction::new(Arc::clone(&self.env))?;
+        let access = tx.access();
+        let mut cursor = tx.cursor(&*self.db)?;
+        let mut pairs = Vec::with_capacity(count);
+        // LMDB has no empty key to seek to
+        let mut entry = if start.is_empty() {
+            cursor.first::<[u8], [u8]>(&access).to_opt()?
+        } else {
+            cursor.seek_range_k::<[u8], [u8]>(&access, start).to_opt()?
+        };
+        while let Some((key, value)) = entry {
+            if pairs.len() == count {
+                break;
+            }
+            pairs.push((key.to_vec(), value.to_vec()));
+            entry = cursor.next::<[u8], [u8]>(&access).to_opt()?;
+        }
+        Ok(pairs)
     }
 
     fn batch(&self) -> Result<Self::Batch, Error> {
@@ -66,6 +102,102 @@ impl<'a> KeyValueStore<'a> for Store {
             tx,
         })
     }
+
+    // MDB_APPEND puts fill the pages in order without searching the tree
+    fn bulk_load<I: Iterator<Item = KeyValue>>(&self, pairs: I) -> Result<(), Error> {
+        let mut pairs = pairs.peekable();
+        while pairs.peek().is_some() {
+            let tx = WriteTransaction::new(Arc::clone(&self.env))?;
+            {
+                let mut access = tx.access();
+                let mut size = 0;
+                for (key, value) in pairs.by_ref() {
+                    access.put(&self.db, &key, &value, lmdb_zero::put::APPEND)?;
+                    size += key.len() + value.len();
+                    if size >= BULK_LOAD_TRANSACTION_SIZE {
+                        break;
+                    }
+                }
+            }
+            tx.commit()?;
+        }
+        Ok(())
+    }
+
+    // keeps one read transaction open, the pages it sees can't be reused by the writers
+    fn snapshot(&'a self) -> Result<Self::Snapshot, Error> {
+        let tx = ReadTransaction::new(Arc::clone(&self.env))?;
+        Ok(LmdbSnapshot {
+            db: Arc::clone(&self.db),
+            tx,
+        })
+    }
+
+    fn compare_and_swap(
+        &self,
+        key: &[u8],
+        old: Option<&[u8]>,
+        new: Option<&[u8]>,
+    ) -> Result<bool, Error> {
+        let tx = WriteTransaction::new(Arc::clone(&self.env))?;
+        {
+            let mut access = tx.access();
+            let current: Option<&[u8]> = access.get(&self.db, key).to_opt()?;
+            if current != old {
+                return Ok(false);
+            }
+            match new {
+                Some(value) => access.put(&self.db, key, value, lmdb_zero::put::Flags::empty())?,
+                None => {
+                    access.del_key(&self.db, key).to_opt()?;
+                }
+            }
+        }
+        tx.commit()?;
+        Ok(true)
+    }
+
+    fn add(&self, key: &[u8], delta: u64) -> Result<(), Error> {
+        let tx = WriteTransaction::new(Arc::clone(&self.env))?;
+        {
+            let mut access = tx.access();
+            let current: Option<&[u8]> = access.get(&self.db, key).to_opt()?;
+            let sum = counter(current).wrapping_add(delta);
+            access.put(
+                &self.db,
+                key,
+                &sum.to_le_bytes(),
+                lmdb_zero::put::Flags::empty(),
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+impl<'a> TransactionalStore<'a> for Store {
+    type Transaction = LmdbTransaction<'a>;
+
+    fn transaction(&'a self) -> Result<Self::Transaction, Error> {
+        let tx = WriteTransaction::new(Arc::clone(&self.env))?;
+        Ok(LmdbTransaction {
+            db: Arc::clone(&self.db),
+            tx,
+        })
+    }
+}
+
+fn get(access: &ConstAccessor, db: &Database, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+    access
+        .get(db, key)
+        .map(|res: &[u8]| res.to_vec())
+        .to_opt()
+        .map_err(Into::into)
+}
+
+fn exists(access: &ConstAccessor, db: &Database, key: &[u8]) -> Result<bool, Error> {
+    let result: lmdb_zero::error::Result<&Ignore> = access.get(db, key);
+    result.to_opt().map(|r| r.is_some()).map_err(Into::into)
 }
 
 pub struct LmdbBatch<'a> {
@@ -81,8 +213,80 @@ impl<'a> Batch for LmdbBatch<'a> {
         Ok(())
     }
 
+    // deleting a missing key isn't an error, as in the other stores
+    fn delete(&mut self, key: &[u8]) -> Result<(), Error> {
+        self.tx.access().del_key(&self.db, key).to_opt()?;
+        Ok(())
+    }
+
+    // LMDB has no range delete, the keys are deleted one by one by a cursor
+    fn delete_range(&mut self, start: &[u8], end: &[u8]) -> Result<(), Error> {
+        let mut cursor = self.tx.cursor(&*self.db)?;
+        let mut access = self.tx.access();
+        let first = if start.is_empty() {
+            cursor.first::<[u8], Ignore>(&access)
+        } else {
+            cursor.seek_range_k::<[u8], Ignore>(&access, start)
+        };
+        let mut entry = first.to_opt()?.map(|(key, _)| key < end);
+        while let Some(true) = entry {
+            cursor.del(&mut access, lmdb_zero::del::Flags::empty())?;
+            // the cursor is already on the next key after a delete
+            entry = cursor
+                .next::<[u8], Ignore>(&access)
+                .to_opt()?
+                .map(|(key, _)| key < end);
+        }
+        Ok(())
+    }
+
+    fn commit(self) -> Result<(), Error> {
+        self.tx.commit()?;
+        Ok(())
+    }
+}
+
+pub struct LmdbSnapshot<'a> {
+    db: Arc<Database<'static>>,
+    tx: ReadTransaction<'a>,
+}
+
+impl<'a> Snapshot for LmdbSnapshot<'a> {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        get(&self.tx.access(), &self.db, key)
+    }
+
+    fn multi_get(&self, keys: &[&[u8]]) -> Result<Vec<Option<Vec<u8>>>, Error> {
+        let access = self.tx.access();
+        keys.iter().map(|key| get(&access, &self.db, key)).collect()
+    }
+
+    fn exists(&self, key: &[u8]) -> Result<bool, Error> {
+        exists(&self.tx.access(), &self.db, key)
+    }
+}
+
+// the same write transaction as a batch, which also reads
+pub struct LmdbTransaction<'a> {
+    db: Arc<Database<'static>>,
+    tx: WriteTransaction<'a>,
+}
+
+impl<'a> Transaction for LmdbTransaction<'a> {
+    fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        get(&self.tx.access(), &self.db, key)
+    }
+
+    fn put(&mut self, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        self.tx
+            .access()
+            .put(&self.db, key, value, lmdb_zero::put::Flags::empty())?;
+        Ok(())
+    }
+
+    // deleting a missing key isn't an error, as in the other stores
     fn delete(&mut self, key: &[u8]) -> Result<(), Error> {
-        self.tx.access().del_key(&self.db, key)?;
+        self.tx.access().del_key(&self.db, key).to_opt()?;
         Ok(())
     }
 
@@ -101,58 +305,55 @@ impl From<lmdb_zero::error::Error> for Error {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use rand::distributions::Standard;
-    use rand::{thread_rng, Rng};
+    use crate::conformance::Capabilities;
     use tempfile;
 
-    #[test]
-    fn put_and_get() {
-        let tmp_dir = tempfile::Builder::new()
-            .prefix("put_and_get")
-            .tempdir()
-            .unwrap();
-        let store = Store::new(tmp_dir.path().to_str().unwrap());
-        let mut batch = store.batch().unwrap();
-        batch.put(&[0, 0], &[0, 0, 0]).unwrap();
-        batch.put(&[1, 1], &[1, 1, 1]).unwrap();
-        let mut rng = thread_rng();
-        // max key size 511 bytes
-        let key: Vec<u8> = rng.sample_iter(&Standard).take(511).collect();
-        let value: Vec<u8> = rng.sample_iter(&Standard).take(1024 * 1024).collect();
-        batch.put(&key, &value).unwrap();
-        batch.commit().unwrap();
+    // LMDB has no empty key
+    fn capabilities() -> Capabilities {
+        Capabilities {
+            empty_key: false,
+            ..Capabilities::default()
+        }
+    }
 
-        assert_eq!(Some(vec![0, 0, 0]), store.get(&[0, 0]).unwrap());
-        assert_eq!(Some(vec![1, 1, 1]), store.get(&[1, 1]).unwrap());
-        assert_eq!(Some(value), store.get(&key).unwrap());
-        assert_eq!(None, store.get(&[2, 2]).unwrap());
+    // the snapshot check reads the store in the thread which holds the snapshot
+    fn open_notls(path: &str) -> impl Fn() -> Store {
+        let path = path.to_string();
+        move || Store::open(&path, &serde_json::json!({ "notls": true })).unwrap()
     }
 
+    crate::conformance_tests!(Store, open_notls, capabilities());
+    crate::conformance::model_tests!(Store, capabilities());
+
     #[test]
-    fn exists() {
-        let tmp_dir = tempfile::Builder::new().prefix("exists").tempdir().unwrap();
+    fn bulk_load() {
+        let tmp_dir = tempfile::Builder::new()
+            .prefix("bulk_load")
+            .tempdir()
+            .unwrap();
         let store = Store::new(tmp_dir.path().to_str().unwrap());
-        assert!(!store.exists(&[0, 0]).unwrap());
-
-        let mut batch = store.batch().unwrap();
-        batch.put(&[0, 0], &[0, 0, 0]).unwrap();
-        batch.commit().unwrap();
-
-        assert!(store.exists(&[0, 0]).unwrap());
+        let pairs = (0..100u64).map(|i| (i.to_be_bytes().to_vec(), vec![i as u8]));
+        store.bulk_load(pairs.clone()).unwrap();
+        assert_eq!(Some(vec![42]), store.get(&42u64.to_be_bytes()).unwrap());
+        assert_eq!(pairs.collect::<Vec<_>>(), store.scan(&[], 1000).unwrap());
+        // appending requires the keys to come after the existing ones
+        assert!(store
+            .bulk_load(vec![(vec![0], vec![0])].into_iter())
+            .is_err());
     }
 
     #[test]
-    fn delete() {
-        let tmp_dir = tempfile::Builder::new().prefix("delete").tempdir().unwrap();
+    fn transaction_reads_its_writes() {
+        let tmp_dir = tempfile::Builder::new()
+            .prefix("transaction_reads_its_writes")
+            .tempdir()
+            .unwrap();
         let store = Store::new(tmp_dir.path().to_str().unwrap());
-        let mut batch = store.batch().unwrap();
-        batch.put(&[0, 0], &[0, 0, 0]).unwrap();
-        batch.commit().unwrap();
+        let mut txn = store.transaction().unwrap();
+        assert_eq!(None, txn.get(&[0, 0]).unwrap());
+        txn.put(&[0, 0], &[0, 0, 0]).unwrap();
+        assert_eq!(Some(vec![0, 0, 0]), txn.get(&[0, 0]).unwrap());
+        txn.commit().unwrap();
         assert_eq!(Some(vec![0, 0, 0]), store.get(&[0, 0]).unwrap());
-
-        let mut batch = store.batch().unwrap();
-        batch.delete(&[0, 0]).unwrap();
-        batch.commit().unwrap();
-        assert_eq!(None, store.get(&[0, 0]).unwrap());
     }
 }
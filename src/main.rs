@@ -1,94 +1,644 @@
-use clap::{App, Arg, ArgMatches, SubCommand};
-use rust_kvstore_bench::lmdb_zero::Store as Lmdb;
-use rust_kvstore_bench::rocksdb::Store as Rocksdb;
-use rust_kvstore_bench::sled::Store as Sled;
+use clap::{App, Arg, ArgGroup, ArgMatches, SubCommand};
+use rand::{thread_rng, Rng};
+use rust_kvstore_bench::dataset::{disk_size, Dataset, DatasetSize, Manifest};
+use rust_kvstore_bench::format::{Format, TaskReader, TaskWriter};
+use rust_kvstore_bench::registry::{self, Backend, Visitor, BACKENDS};
+use rust_kvstore_bench::remote::{self, Listener};
 use rust_kvstore_bench::workload::{
-    generate_report, generate_workload, run_workload, sample_workload, TaskGenerator, Workload,
-    WorkloadResult,
+    generate_report, generate_workload, run_contention, run_load, run_snapshot_reads, run_workload,
+    sample_workload, CollectedKeys, GeneratedKeys, Task, TaskGenerator, WorkloadResult,
 };
-use rust_kvstore_bench::KeyValueStore;
-use serde_json;
-use std::io::{stdin, stdout};
+use rust_kvstore_bench::{KeyValueStore, TransactionalStore};
+use std::io::{stdin, stdout, BufRead, BufWriter};
+use std::path::Path;
+use std::sync::mpsc;
+use std::thread;
 
 fn main() {
+    let format_arg = Arg::with_name("format")
+        .long("format")
+        .takes_value(true)
+        .possible_values(&["json", "binary"])
+        .default_value("json")
+        .help("Output workload format");
+    let seed_arg = Arg::with_name("seed")
+        .long("seed")
+        .takes_value(true)
+        .help("Seed of the random generator");
+    // the workloads of the files are random without a seed, the others are reproducible
+    let random_seed_arg = seed_arg
+        .clone()
+        .help("Seed of the random generator, a random seed is used by default");
+    let datasets_arg = Arg::with_name("datasets")
+        .long("datasets")
+        .takes_value(true)
+        .default_value("datasets")
+        .help("Directory of the datasets");
+    let version_arg = Arg::with_name("version")
+        .long("version")
+        .takes_value(true)
+        .default_value("1")
+        .help("Version of the dataset");
+    let options_arg = Arg::with_name("options")
+        .long("options")
+        .takes_value(true)
+        .help(
+            "Options of the store as a json object, e.g. '{\"durability\":\"immediate\"}' for redb",
+        );
+    let names = |filter: fn(&Backend) -> bool| -> Vec<&str> {
+        BACKENDS
+            .iter()
+            .filter(|backend| filter(backend))
+            .map(|backend| backend.name)
+            .collect()
+    };
+    let (all, persistent, transactional) = (
+        names(|_| true),
+        names(|backend| backend.persistent),
+        names(|backend| backend.transactional),
+    );
+    let db_type_arg = Arg::with_name("db_type")
+        .required(true)
+        .help("Backend, see list-backends");
+    let values_arg = Arg::with_name("values")
+        .long("values")
+        .takes_value(true)
+        .help("Value generator of the puts, e.g. '{\"compression_ratio\":0.5}'");
     let matches = App::new("Rust Key Value Store Benchmark")
         .version("0.1.0")
         .subcommand(
             SubCommand::with_name("generate_workload")
                 .about("Generates a benchmark workload")
                 .arg(Arg::with_name("task_generator").required(true))
-                .arg(Arg::with_name("nums_task").required(true)),
+                .arg(Arg::with_name("nums_task").required(true))
+                .arg(format_arg.clone())
+                .arg(random_seed_arg.clone())
+                .arg(values_arg.clone()),
         )
         .subcommand(
             SubCommand::with_name("sample_workload")
                 .about("Take samples of generated workload")
                 .arg(Arg::with_name("task_generator").required(true))
-                .arg(Arg::with_name("nums_task").required(true)),
+                .arg(Arg::with_name("nums_task").required(true))
+                .arg(format_arg.clone())
+                .arg(random_seed_arg)
+                .arg(values_arg.clone()),
+        )
+        .subcommand(
+            SubCommand::with_name("convert_workload")
+                .about("Convert a workload between json and binary formats")
+                .arg(format_arg),
         )
         .subcommand(
             SubCommand::with_name("run")
                 .about("Run a workload on the database")
-                .arg(Arg::with_name("db_type").required(true))
-                .arg(Arg::with_name("path").required(true)),
+                .arg(db_type_arg.clone().possible_values(&all))
+                .arg(Arg::with_name("path").required(true))
+                .arg(options_arg.clone())
+                .arg(
+                    Arg::with_name("generate")
+                        .long("generate")
+                        .takes_value(true)
+                        .value_names(&["task_generator", "nums_task"])
+                        .help("Generates the workload on the fly instead of reading it from stdin"),
+                )
+                .arg(
+                    Arg::with_name("dataset")
+                        .long("dataset")
+                        .takes_value(true)
+                        .help("Copies the dataset to the path and samples its keys as for --generate"),
+                )
+                .group(ArgGroup::with_name("keys").args(&["generate", "dataset"]))
+                .arg(datasets_arg.clone())
+                .arg(version_arg.clone())
+                .arg(
+                    Arg::with_name("sample")
+                        .long("sample")
+                        .takes_value(true)
+                        .value_names(&["task_generator", "nums_task"])
+                        .requires("keys")
+                        .help("Samples the keys of the generated workload instead of running it"),
+                )
+                .arg(
+                    Arg::with_name("prune")
+                        .long("prune")
+                        .takes_value(true)
+                        .value_names(&["task_generator", "nums_task"])
+                        .requires("keys")
+                        .help("Samples tasks of the generated keys which run first, e.g. deletes of a key range"),
+                )
+                .arg(
+                    Arg::with_name("snapshot")
+                        .long("snapshot")
+                        .takes_value(true)
+                        .value_names(&["task_generator", "nums_task"])
+                        .requires("dataset")
+                        .help("Samples reads of the keys of the dataset and runs them on one snapshot, concurrently with the workload"),
+                )
+                .arg(seed_arg.clone().default_value("0"))
+                .arg(values_arg.clone().requires("keys")),
+        )
+        .subcommand(
+            SubCommand::with_name("build_dataset")
+                .about("Builds a dataset with a seeded workload, to copy it for read-only runs")
+                .arg(Arg::with_name("name").required(true))
+                .arg(db_type_arg.clone().possible_values(&persistent))
+                .arg(Arg::with_name("task_generator").required(true))
+                .arg(Arg::with_name("nums_task").required(true))
+                .arg(options_arg.clone())
+                .arg(datasets_arg)
+                .arg(version_arg)
+                .arg(seed_arg.clone().default_value("0"))
+                .arg(values_arg),
+        )
+        .subcommand(
+            SubCommand::with_name("load")
+                .about("Fills the database with sequential keys by a bulk load")
+                .arg(db_type_arg.clone().possible_values(&all))
+                .arg(Arg::with_name("path").required(true))
+                .arg(options_arg.clone())
+                .arg(Arg::with_name("nums_key").required(true))
+                .arg(
+                    Arg::with_name("key_size")
+                        .long("key_size")
+                        .takes_value(true)
+                        .default_value("32"),
+                )
+                .arg(
+                    Arg::with_name("value_size")
+                        .long("value_size")
+                        .takes_value(true)
+                        .default_value("4096")
+                        .help("Value size or size distribution, e.g. '{\"uniform\":[100,200]}'"),
+                )
+                .arg(
+                    Arg::with_name("values")
+                        .long("values")
+                        .takes_value(true)
+                        .help("Value generator, e.g. '{\"compression_ratio\":0.5}'"),
+                )
+                .arg(seed_arg.clone().default_value("0")),
+        )
+        .subcommand(
+            SubCommand::with_name("serve")
+                .about("Serves the database to the remote db_type of run and load")
+                .arg(db_type_arg.clone().possible_values(&all))
+                .arg(Arg::with_name("path").required(true))
+                .arg(
+                    Arg::with_name("address")
+                        .required(true)
+                        .help("TCP address, e.g. 127.0.0.1:7878, or else path of a Unix socket"),
+                )
+                .arg(options_arg.clone()),
+        )
+        .subcommand(
+            SubCommand::with_name("contend")
+                .about("Increments hot counters from concurrent transactions")
+                .arg(db_type_arg.clone().possible_values(&transactional))
+                .arg(Arg::with_name("path").required(true))
+                .arg(options_arg)
+                .arg(
+                    Arg::with_name("nums_task")
+                        .required(true)
+                        .help("Transactions of each thread"),
+                )
+                .arg(
+                    Arg::with_name("threads")
+                        .long("threads")
+                        .takes_value(true)
+                        .default_value("4"),
+                )
+                .arg(
+                    Arg::with_name("hot_keys")
+                        .long("hot_keys")
+                        .takes_value(true)
+                        .default_value("16")
+                        .help("Number of counters"),
+                )
+                .arg(
+                    Arg::with_name("keys_per_transaction")
+                        .long("keys_per_transaction")
+                        .takes_value(true)
+                        .default_value("2")
+                        .help("Counters incremented by each transaction"),
+                )
+                .arg(seed_arg.default_value("0")),
+        )
+        .subcommand(
+            SubCommand::with_name("list_backends")
+                .visible_alias("list-backends")
+                .about("Lists the backends compiled in, the db_type of the other subcommands"),
+        )
+        .subcommand(
+            SubCommand::with_name("report")
+                .about("Generate report")
+                .arg(
+                    Arg::with_name("task_type")
+                        .long("task_type")
+                        .takes_value(true)
+                        .possible_values(&[
+                            "get",
+                            "exists",
+                            "multi_get",
+                            "scan",
+                            "batch",
+                            "read_modify_write",
+                            "increment",
+                            "transaction",
+                        ])
+                        .help("Only reports the tasks of this type"),
+                ),
         )
-        .subcommand(SubCommand::with_name("report").about("Generate report"))
         .get_matches();
 
     match matches.subcommand() {
-        ("generate_workload", Some(matches)) => execute_generate_workload(&matches),
-        ("sample_workload", Some(matches)) => execute_sample_workload(&matches),
-        ("run", Some(matches)) => execute_run(&matches),
-        ("report", _) => execute_report(),
+        ("generate_workload", Some(matches)) => execute_generate_workload(matches),
+        ("sample_workload", Some(matches)) => execute_sample_workload(matches),
+        ("convert_workload", Some(matches)) => execute_convert_workload(matches),
+        ("run", Some(matches)) => execute_store(Command::Run, matches),
+        ("build_dataset", Some(matches)) => execute_store(Command::BuildDataset, matches),
+        ("load", Some(matches)) => execute_store(Command::Load, matches),
+        ("serve", Some(matches)) => execute_store(Command::Serve, matches),
+        ("contend", Some(matches)) => execute_store(Command::Contend, matches),
+        ("list_backends", Some(_)) => execute_list_backends(),
+        ("report", Some(matches)) => execute_report(matches),
         _ => {}
     }
 }
 
 fn execute_generate_workload(matches: &ArgMatches) {
     let task_generator: TaskGenerator =
-        serde_json::from_str(&matches.value_of("task_generator").unwrap()).expect("invalid json");
+        serde_json::from_str(matches.value_of("task_generator").unwrap()).expect("invalid json");
     let nums_task: usize = matches
         .value_of("nums_task")
         .unwrap()
         .parse()
         .expect("invalid num");
-    let workload = generate_workload(&task_generator, nums_task);
-    serde_json::to_writer(stdout(), &workload).expect("failed to write workload");
+    let tasks = generate_workload(&task_generator, nums_task, parse_seed(matches))
+        .expect("invalid workload");
+    write_tasks(matches, with_values(matches, tasks));
 }
 
 fn execute_sample_workload(matches: &ArgMatches) {
-    let workload: Workload = serde_json::from_reader(stdin()).expect("failed to read workload");
     let task_generator: TaskGenerator =
-        serde_json::from_str(&matches.value_of("task_generator").unwrap()).expect("invalid json");
+        serde_json::from_str(matches.value_of("task_generator").unwrap()).expect("invalid json");
     let nums_task: usize = matches
         .value_of("nums_task")
         .unwrap()
         .parse()
         .expect("invalid num");
-    let workload = sample_workload(&workload, &task_generator, nums_task);
-    serde_json::to_writer(stdout(), &workload).expect("failed to write workload");
+    let input = stdin();
+    let keys = CollectedKeys::from_tasks(read_tasks(input.lock()));
+    let tasks = sample_workload(&keys, &task_generator, nums_task, parse_seed(matches))
+        .expect("invalid workload");
+    write_tasks(matches, with_values(matches, tasks));
 }
 
-fn execute_run(matches: &ArgMatches) {
+fn execute_convert_workload(matches: &ArgMatches) {
+    let input = stdin();
+    write_tasks(matches, read_tasks(input.lock()));
+}
+
+// the subcommands which open a store, run with the type of the store of their db_type
+enum Command<'m> {
+    Run(&'m ArgMatches<'m>),
+    BuildDataset(&'m ArgMatches<'m>),
+    Load(&'m ArgMatches<'m>),
+    Serve(&'m ArgMatches<'m>),
+    Contend(&'m ArgMatches<'m>),
+}
+
+impl Visitor for Command<'_> {
+    type Output = ();
+
+    fn visit<T>(self, backend: &Backend)
+    where
+        T: for<'a> KeyValueStore<'a> + Sync,
+    {
+        match self {
+            Command::Run(matches) => run::<T>(matches.value_of("path").unwrap(), matches),
+            Command::BuildDataset(matches) => build_dataset::<T>(matches),
+            Command::Load(matches) => load::<T>(matches.value_of("path").unwrap(), matches),
+            Command::Serve(matches) => serve::<T>(matches.value_of("path").unwrap(), matches),
+            Command::Contend(_) => panic!("{} has no transactions", backend.name),
+        }
+    }
+
+    fn visit_transactional<T>(self, backend: &Backend)
+    where
+        T: for<'a> TransactionalStore<'a> + Sync,
+    {
+        match self {
+            Command::Contend(matches) => contend::<T>(matches.value_of("path").unwrap(), matches),
+            command => command.visit::<T>(backend),
+        }
+    }
+}
+
+fn execute_store<'m>(command: fn(&'m ArgMatches<'m>) -> Command<'m>, matches: &'m ArgMatches<'m>) {
     let db_type = matches.value_of("db_type").unwrap();
-    let path = matches.value_of("path").unwrap();
-    match db_type {
-        "rocksdb" => run::<Rocksdb>(&path),
-        "lmdb" => run::<Lmdb>(&path),
-        "sled" => run::<Sled>(&path),
-        _ => {}
+    registry::visit(db_type, command(matches)).expect("unknown db_type");
+}
+
+fn execute_list_backends() {
+    for backend in BACKENDS {
+        let mut tags = String::new();
+        if backend.transactional {
+            tags.push_str(" [transactions]");
+        }
+        if backend.persistent {
+            tags.push_str(" [datasets]");
+        }
+        println!("{:<20}{}{}", backend.name, backend.description, tags);
     }
 }
 
-fn execute_report() {
+fn execute_report(matches: &ArgMatches) {
     let result: WorkloadResult =
         serde_json::from_reader(stdin()).expect("failed to read workload result");
-    let report = generate_report(&result);
+    let task_type = matches.value_of("task_type").map(|task_type| {
+        serde_json::from_value(serde_json::Value::String(task_type.to_string())).unwrap()
+    });
+    let report = generate_report(&result, task_type);
     serde_json::to_writer_pretty(stdout(), &report).expect("failed to write report");
 }
 
-fn run<'a, T: KeyValueStore<'a>>(path: &str) {
-    let store = T::new(path);
-    let workload: Workload = serde_json::from_reader(stdin()).expect("failed to read workload");
-    let result = run_workload(&workload, &store);
+fn run<T>(path: &str, matches: &ArgMatches)
+where
+    T: for<'a> KeyValueStore<'a> + Sync,
+{
+    // the dataset is copied before the store opens it with the options which built it, its
+    // workload is regenerated
+    let (generated, options) = match matches.value_of("dataset") {
+        Some(name) => {
+            let dataset = dataset(name, matches);
+            let manifest = dataset.manifest().expect("failed to read dataset");
+            assert_eq!(
+                manifest.db_type,
+                matches.value_of("db_type").unwrap(),
+                "the dataset was built for another db_type"
+            );
+            if matches.is_present("options") {
+                assert_eq!(
+                    manifest.options,
+                    parse_options(matches),
+                    "the dataset was built with other options"
+                );
+            }
+            dataset.copy_to(path).expect("failed to copy dataset");
+            let generated = (manifest.task_generator, manifest.nums_task, manifest.seed);
+            (Some(generated), manifest.options)
+        }
+        None => (
+            matches.values_of("generate").map(|mut values| {
+                let (task_generator, nums_task) = parse_generator(&mut values);
+                (task_generator, nums_task, parse_seed(matches))
+            }),
+            parse_options(matches),
+        ),
+    };
+    let store = T::open(path, &options).expect("failed to open store");
+    // the pages kept for the snapshot can't be reused, the store grows instead
+    let size = matches
+        .is_present("snapshot")
+        .then(|| disk_size(Path::new(path)).expect("failed to measure store"));
+    let result = match generated {
+        Some((task_generator, nums_task, seed)) => {
+            let keys =
+                GeneratedKeys::new(&task_generator, nums_task, seed).expect("invalid workload");
+            thread::scope(|scope| {
+                let reader = matches.values_of("snapshot").map(|mut values| {
+                    let (read_generator, read_nums_task) = parse_generator(&mut values);
+                    let (store, keys) = (&store, &keys);
+                    let (taken, wait) = mpsc::channel();
+                    let reader = scope.spawn(move || {
+                        let snapshot = store.snapshot().expect("failed to take snapshot");
+                        taken.send(()).unwrap();
+                        let tasks = sample_workload(keys, &read_generator, read_nums_task, seed)
+                            .expect("invalid workload");
+                        run_snapshot_reads(tasks, &snapshot)
+                    });
+                    // the workload starts writing after the snapshot is taken
+                    wait.recv().expect("failed to take snapshot");
+                    reader
+                });
+                // the reads and scans after a prune go through its tombstones
+                let mut result = match matches.values_of("prune") {
+                    Some(mut values) => {
+                        let (prune_generator, prune_nums_task) = parse_generator(&mut values);
+                        let tasks = sample_workload(&keys, &prune_generator, prune_nums_task, seed)
+                            .expect("invalid workload");
+                        run_workload(tasks, &store)
+                    }
+                    None => WorkloadResult(Vec::new()),
+                };
+                result.0.extend(
+                    match matches.values_of("sample") {
+                        Some(mut values) => {
+                            let (sample_generator, sample_nums_task) = parse_generator(&mut values);
+                            let tasks =
+                                sample_workload(&keys, &sample_generator, sample_nums_task, seed)
+                                    .expect("invalid workload");
+                            run_workload(with_values(matches, tasks), &store)
+                        }
+                        None => {
+                            let tasks = generate_workload(&task_generator, nums_task, seed)
+                                .expect("invalid workload");
+                            run_workload(with_values(matches, tasks), &store)
+                        }
+                    }
+                    .0,
+                );
+                if let Some(reader) = reader {
+                    result.0.extend(reader.join().unwrap().0);
+                }
+                result
+            })
+        }
+        None => {
+            let input = stdin();
+            run_workload(read_tasks(input.lock()), &store)
+        }
+    };
+    if let Some(size) = size {
+        let grown = disk_size(Path::new(path)).expect("failed to measure store");
+        eprintln!(
+            "the store grew from {} to {} bytes during the snapshot",
+            size, grown
+        );
+    }
+    serde_json::to_writer(stdout(), &result).expect("failed to write workload result");
+}
+
+fn build_dataset<T>(matches: &ArgMatches)
+where
+    T: for<'a> KeyValueStore<'a>,
+{
+    let name = matches.value_of("name").unwrap();
+    let dataset = dataset(name, matches);
+    let db_path = dataset.create().expect("failed to create dataset");
+    let (task_generator, nums_task) = parse_generator(
+        &mut vec![
+            matches.value_of("task_generator").unwrap(),
+            matches.value_of("nums_task").unwrap(),
+        ]
+        .into_iter(),
+    );
+    let seed = parse_seed(matches);
+    let mut size = DatasetSize::default();
+    let result = {
+        let store: T = open(db_path.to_str().unwrap(), matches);
+        let tasks = generate_workload(&task_generator, nums_task, seed).expect("invalid workload");
+        run_workload(
+            with_values(matches, tasks).inspect(|task| size.add(task)),
+            &store,
+        )
+    };
+    let manifest = Manifest {
+        name: name.to_string(),
+        version: matches.value_of("version").unwrap().parse().unwrap(),
+        db_type: matches.value_of("db_type").unwrap().to_string(),
+        task_generator,
+        nums_task,
+        seed,
+        values: matches
+            .value_of("values")
+            .map(|values| serde_json::from_str(values).expect("invalid json"))
+            .unwrap_or_default(),
+        options: parse_options(matches),
+        size,
+    };
+    dataset
+        .write_manifest(&manifest)
+        .expect("failed to write dataset manifest");
     serde_json::to_writer(stdout(), &result).expect("failed to write workload result");
 }
+
+fn open<T>(path: &str, matches: &ArgMatches) -> T
+where
+    T: for<'a> KeyValueStore<'a>,
+{
+    T::open(path, &parse_options(matches)).expect("failed to open store")
+}
+
+fn parse_options(matches: &ArgMatches) -> serde_json::Value {
+    matches
+        .value_of("options")
+        .map(|options| serde_json::from_str(options).expect("invalid json"))
+        .unwrap_or_default()
+}
+
+fn dataset(name: &str, matches: &ArgMatches) -> Dataset {
+    let version = matches
+        .value_of("version")
+        .unwrap()
+        .parse()
+        .expect("invalid version");
+    Dataset::new(
+        matches.value_of("datasets").unwrap(),
+        name,
+        version,
+        matches.value_of("db_type").unwrap(),
+    )
+}
+
+fn load<T>(path: &str, matches: &ArgMatches)
+where
+    T: for<'a> KeyValueStore<'a>,
+{
+    let store: T = open(path, matches);
+    let parse = |name| -> usize {
+        matches
+            .value_of(name)
+            .unwrap()
+            .parse()
+            .expect("invalid num")
+    };
+    let value_size =
+        serde_json::from_str(matches.value_of("value_size").unwrap()).expect("invalid json");
+    let values = matches
+        .value_of("values")
+        .map(|values| serde_json::from_str(values).expect("invalid json"))
+        .unwrap_or_default();
+    let report = run_load(
+        &store,
+        parse("nums_key"),
+        parse("key_size"),
+        &value_size,
+        &values,
+        parse_seed(matches),
+    );
+    serde_json::to_writer_pretty(stdout(), &report).expect("failed to write load report");
+}
+
+fn serve<T>(path: &str, matches: &ArgMatches)
+where
+    T: for<'a> KeyValueStore<'a> + Sync,
+{
+    let store: T = open(path, matches);
+    let listener =
+        Listener::bind(matches.value_of("address").unwrap()).expect("failed to bind address");
+    remote::serve(&store, listener).expect("failed to serve store");
+}
+
+fn contend<T>(path: &str, matches: &ArgMatches)
+where
+    T: for<'a> TransactionalStore<'a> + Sync,
+{
+    let store: T = open(path, matches);
+    let parse = |name| -> usize {
+        matches
+            .value_of(name)
+            .unwrap()
+            .parse()
+            .expect("invalid num")
+    };
+    let result = run_contention(
+        &store,
+        parse("threads"),
+        parse("nums_task"),
+        parse("hot_keys"),
+        parse("keys_per_transaction"),
+        parse_seed(matches),
+    );
+    serde_json::to_writer(stdout(), &result).expect("failed to write workload result");
+}
+
+fn parse_generator<'a, I: Iterator<Item = &'a str>>(values: &mut I) -> (TaskGenerator, usize) {
+    let task_generator = serde_json::from_str(values.next().unwrap()).expect("invalid json");
+    let nums_task = values.next().unwrap().parse().expect("invalid num");
+    (task_generator, nums_task)
+}
+
+// records the value generator in the workload, before the tasks which use it
+fn with_values<I: Iterator<Item = Task>>(
+    matches: &ArgMatches,
+    tasks: I,
+) -> impl Iterator<Item = Task> {
+    let values = matches
+        .value_of("values")
+        .map(|values| Task::Values(serde_json::from_str(values).expect("invalid json")));
+    values.into_iter().chain(tasks)
+}
+
+fn parse_seed(matches: &ArgMatches) -> u64 {
+    matches
+        .value_of("seed")
+        .map(|seed| seed.parse().expect("invalid seed"))
+        .unwrap_or_else(|| thread_rng().gen())
+}
+
+fn read_tasks<R: BufRead>(input: R) -> impl Iterator<Item = Task> {
+    TaskReader::new(input)
+        .expect("failed to read workload")
+        .map(|task| task.expect("failed to read workload"))
+}
+
+fn write_tasks<I: IntoIterator<Item = Task>>(matches: &ArgMatches, tasks: I) {
+    let format: Format = matches.value_of("format").unwrap().parse().unwrap();
+    let output = stdout();
+    let mut writer =
+        TaskWriter::new(BufWriter::new(output.lock()), format).expect("failed to write workload");
+    writer.write_all(tasks).expect("failed to write workload");
+    writer.finish().expect("failed to write workload");
+}
@@ -0,0 +1,372 @@
+use crate::{
+    counter, parse_options, Batch, Error, KeyValue, KeyValueStore, Snapshot, Transaction,
+    TransactionalStore,
+};
+use libmdbx::{
+    Database, DatabaseFlags, Geometry, Mode, NoWriteMap, SyncMode, TransactionKind, WriteFlags, RO,
+    RW,
+};
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+type Env = Database<NoWriteMap>;
+type MdbxTx<'a, K> = libmdbx::Transaction<'a, K, NoWriteMap>;
+
+// bytes of keys and values put by each write transaction of a bulk load
+const BULK_LOAD_TRANSACTION_SIZE: usize = 64 << 20;
+
+/// Options of `--options`, e.g.
+/// `{"geometry":{"size_upper":1099511627776,"growth_step":1073741824},"sync_mode":"durable","liforeclaim":true}`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MdbxOptions {
+    geometry: MdbxGeometry,
+    sync_mode: MdbxSyncMode,
+    // reuses the pages freed last first, which are likely still in the disk cache
+    liforeclaim: bool,
+}
+
+// the map grows from `size_lower` to `size_upper` by `growth_step` bytes, and shrinks by
+// `shrink_threshold` bytes, MDBX's defaults when missing
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct MdbxGeometry {
+    size_lower: Option<usize>,
+    size_upper: usize,
+    growth_step: Option<isize>,
+    shrink_threshold: Option<isize>,
+}
+
+impl Default for MdbxGeometry {
+    // the same max size as the LMDB map
+    fn default() -> Self {
+        Self {
+            size_lower: None,
+            size_upper: 1 << 40,
+            growth_step: None,
+            shrink_threshold: None,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum MdbxSyncMode {
+    Durable,
+    NoMetaSync,
+    SafeNoSync,
+    #[default]
+    UtterlyNoSync,
+}
+
+impl From<MdbxSyncMode> for SyncMode {
+    fn from(sync_mode: MdbxSyncMode) -> SyncMode {
+        match sync_mode {
+            MdbxSyncMode::Durable => SyncMode::Durable,
+            MdbxSyncMode::NoMetaSync => SyncMode::NoMetaSync,
+            MdbxSyncMode::SafeNoSync => SyncMode::SafeNoSync,
+            MdbxSyncMode::UtterlyNoSync => SyncMode::UtterlyNoSync,
+        }
+    }
+}
+
+/// MDBX, a fork of LMDB, in the main table of an environment of its own, to compare it
+/// with `lmdb_zero::Store` on the same workloads.
+pub struct Store {
+    env: Arc<Env>,
+}
+
+impl<'a> KeyValueStore<'a> for Store {
+    type Batch = MdbxBatch;
+    type Snapshot = MdbxSnapshot<'a>;
+
+    fn new(path: &str) -> Self {
+        Self::open(path, &serde_json::Value::Null).expect("Failed to open mdbx")
+    }
+
+    fn open(path: &str, options: &serde_json::Value) -> Result<Self, Error> {
+        let options: MdbxOptions = parse_options(options)?;
+        fs::create_dir_all(path)?;
+        let geometry = options.geometry;
+        let env = Env::new()
+            .set_geometry(Geometry {
+                size: Some(geometry.size_lower.unwrap_or(0)..geometry.size_upper),
+                growth_step: geometry.growth_step,
+                shrink_threshold: geometry.shrink_threshold,
+                page_size: None,
+            })
+            // NOTLS is always set, a thread which holds a snapshot can open other read
+            // transactions
+            .set_flags(DatabaseFlags {
+                mode: Mode::ReadWrite {
+                    sync_mode: options.sync_mode.into(),
+                },
+                liforeclaim: options.liforeclaim,
+                ..DatabaseFlags::default()
+            })
+            .open(Path::new(path))?;
+        Ok(Self { env: Arc::new(env) })
+    }
+
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        get(&self.env.begin_ro_txn()?, key)
+    }
+
+    // all the keys are read by one transaction instead of one per key
+    fn multi_get(&self, keys: &[&[u8]]) -> Result<Vec<Option<Vec<u8>>>, Error> {
+        let tx = self.env.begin_ro_txn()?;
+        keys.iter().map(|key| get(&tx, key)).collect()
+    }
+
+    fn exists(&self, key: &[u8]) -> Result<bool, Error> {
+        exists(&self.env.begin_ro_txn()?, key)
+    }
+
+    fn scan(&self, start: &[u8], count: usize) -> Result<Vec<KeyValue>, Error> {
+        let tx = self.env.begin_ro_txn()?;
+        let table = tx.open_table(None)?;
+        let mut cursor = tx.cursor(&table)?;
+        let mut pairs = Vec::with_capacity(count);
+        let mut entry = cursor.set_range::<Vec<u8>, Vec<u8>>(start)?;
+        while let Some(pair) = entry {
+            if pairs.len() == count {
+                break;
+            }
+            pairs.push(pair);
+            entry = cursor.next()?;
+        }
+        Ok(pairs)
+    }
+
+    fn batch(&self) -> Result<Self::Batch, Error> {
+        Ok(MdbxBatch {
+            env: Arc::clone(&self.env),
+            writes: Vec::new(),
+        })
+    }
+
+    // MDBX_APPEND puts fill the pages in order without searching the tree
+    fn bulk_load<I: Iterator<Item = KeyValue>>(&self, pairs: I) -> Result<(), Error> {
+        let mut pairs = pairs.peekable();
+        while pairs.peek().is_some() {
+            let tx = self.env.begin_rw_txn()?;
+            {
+                let table = tx.open_table(None)?;
+                let mut size = 0;
+                for (key, value) in pairs.by_ref() {
+                    tx.put(&table, &key, &value, WriteFlags::APPEND)?;
+                    size += key.len() + value.len();
+                    if size >= BULK_LOAD_TRANSACTION_SIZE {
+                        break;
+                    }
+                }
+            }
+            tx.commit()?;
+        }
+        Ok(())
+    }
+
+    // keeps one read transaction open, the pages it sees can't be reused by the writers
+    fn snapshot(&'a self) -> Result<Self::Snapshot, Error> {
+        Ok(MdbxSnapshot {
+            tx: self.env.begin_ro_txn()?,
+        })
+    }
+
+    fn compare_and_swap(
+        &self,
+        key: &[u8],
+        old: Option<&[u8]>,
+        new: Option<&[u8]>,
+    ) -> Result<bool, Error> {
+        let tx = self.env.begin_rw_txn()?;
+        if get(&tx, key)?.as_deref() != old {
+            return Ok(false);
+        }
+        match new {
+            Some(value) => put(&tx, key, value)?,
+            None => delete(&tx, key)?,
+        }
+        tx.commit()?;
+        Ok(true)
+    }
+
+    fn add(&self, key: &[u8], delta: u64) -> Result<(), Error> {
+        let tx = self.env.begin_rw_txn()?;
+        let sum = counter(get(&tx, key)?.as_deref()).wrapping_add(delta);
+        put(&tx, key, &sum.to_le_bytes())?;
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+impl<'a> TransactionalStore<'a> for Store {
+    type Transaction = MdbxTransaction<'a>;
+
+    fn transaction(&'a self) -> Result<Self::Transaction, Error> {
+        Ok(MdbxTransaction {
+            tx: self.env.begin_rw_txn()?,
+        })
+    }
+}
+
+fn get<K: TransactionKind>(tx: &MdbxTx<K>, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+    Ok(tx.get(&tx.open_table(None)?, key)?)
+}
+
+// the value is not copied
+fn exists<K: TransactionKind>(tx: &MdbxTx<K>, key: &[u8]) -> Result<bool, Error> {
+    Ok(tx.get::<()>(&tx.open_table(None)?, key)?.is_some())
+}
+
+fn put(tx: &MdbxTx<RW>, key: &[u8], value: &[u8]) -> Result<(), Error> {
+    tx.put(&tx.open_table(None)?, key, value, WriteFlags::empty())?;
+    Ok(())
+}
+
+fn delete(tx: &MdbxTx<RW>, key: &[u8]) -> Result<(), Error> {
+    tx.del(&tx.open_table(None)?, key, None)?;
+    Ok(())
+}
+
+// MDBX has no range delete, the keys are deleted one by one by a cursor
+fn delete_range(tx: &MdbxTx<RW>, start: &[u8], end: &[u8]) -> Result<(), Error> {
+    let table = tx.open_table(None)?;
+    let mut cursor = tx.cursor(&table)?;
+    let mut entry = cursor.set_range::<Cow<[u8]>, ()>(start)?;
+    while let Some((key, ())) = entry {
+        if &*key >= end {
+            break;
+        }
+        cursor.del(WriteFlags::empty())?;
+        // the cursor is already on the next key after a delete
+        entry = cursor.next()?;
+    }
+    Ok(())
+}
+
+enum Write {
+    Put(Vec<u8>, Vec<u8>),
+    Delete(Vec<u8>),
+    DeleteRange(Vec<u8>, Vec<u8>),
+}
+
+// a write transaction borrows the environment, so the writes are buffered and applied
+// by one write transaction when committing
+pub struct MdbxBatch {
+    env: Arc<Env>,
+    writes: Vec<Write>,
+}
+
+impl Batch for MdbxBatch {
+    fn put(&mut self, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        self.writes.push(Write::Put(key.to_vec(), value.to_vec()));
+        Ok(())
+    }
+
+    fn delete(&mut self, key: &[u8]) -> Result<(), Error> {
+        self.writes.push(Write::Delete(key.to_vec()));
+        Ok(())
+    }
+
+    fn delete_range(&mut self, start: &[u8], end: &[u8]) -> Result<(), Error> {
+        self.writes
+            .push(Write::DeleteRange(start.to_vec(), end.to_vec()));
+        Ok(())
+    }
+
+    fn commit(self) -> Result<(), Error> {
+        let tx = self.env.begin_rw_txn()?;
+        for write in &self.writes {
+            match write {
+                Write::Put(key, value) => put(&tx, key, value)?,
+                Write::Delete(key) => delete(&tx, key)?,
+                Write::DeleteRange(start, end) => delete_range(&tx, start, end)?,
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+pub struct MdbxSnapshot<'a> {
+    tx: MdbxTx<'a, RO>,
+}
+
+impl<'a> Snapshot for MdbxSnapshot<'a> {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        get(&self.tx, key)
+    }
+
+    fn multi_get(&self, keys: &[&[u8]]) -> Result<Vec<Option<Vec<u8>>>, Error> {
+        keys.iter().map(|key| get(&self.tx, key)).collect()
+    }
+
+    fn exists(&self, key: &[u8]) -> Result<bool, Error> {
+        exists(&self.tx, key)
+    }
+}
+
+pub struct MdbxTransaction<'a> {
+    tx: MdbxTx<'a, RW>,
+}
+
+impl<'a> Transaction for MdbxTransaction<'a> {
+    fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        get(&self.tx, key)
+    }
+
+    fn put(&mut self, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        put(&self.tx, key, value)
+    }
+
+    fn delete(&mut self, key: &[u8]) -> Result<(), Error> {
+        delete(&self.tx, key)
+    }
+
+    fn commit(self) -> Result<(), Error> {
+        self.tx.commit()?;
+        Ok(())
+    }
+}
+
+impl From<libmdbx::Error> for Error {
+    fn from(e: libmdbx::Error) -> Error {
+        Error::DBError(e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile;
+
+    crate::conformance_tests!(Store);
+    crate::conformance::model_tests!(Store);
+
+    #[test]
+    fn options() {
+        let tmp_dir = tempfile::Builder::new()
+            .prefix("options")
+            .tempdir()
+            .unwrap();
+        let path = tmp_dir.path().to_str().unwrap();
+        assert!(Store::open(path, &serde_json::json!({"mapsize": 1 << 30})).is_err());
+        let options = serde_json::json!({
+            "geometry": {"size_upper": 1 << 30, "growth_step": 1 << 20},
+            "sync_mode": "durable",
+            "liforeclaim": true
+        });
+        let store = Store::open(path, &options).unwrap();
+        let mut batch = store.batch().unwrap();
+        batch.put(&[0, 0], &[0, 0, 0]).unwrap();
+        batch.commit().unwrap();
+        drop(store);
+
+        let store = Store::new(path);
+        assert_eq!(Some(vec![0, 0, 0]), store.get(&[0, 0]).unwrap());
+    }
+}
@@ -0,0 +1,261 @@
+use crate::{
+    counter, Batch, Error, KeyValue, KeyValueStore, Snapshot, Transaction, TransactionalStore,
+};
+use std::collections::BTreeMap;
+use std::ops::Bound;
+use std::sync::{Arc, RwLock};
+
+type Map = BTreeMap<Vec<u8>, Vec<u8>>;
+
+/// An in-memory store which never touches the disk, its latency is the overhead of the
+/// benchmark harness, and its results are the expected ones of the other stores.
+pub struct Store {
+    // a snapshot shares the map, the next write copies it
+    map: Arc<RwLock<Arc<Map>>>,
+}
+
+impl<'a> KeyValueStore<'a> for Store {
+    type Batch = MemoryBatch;
+    type Snapshot = MemorySnapshot;
+
+    // the path is ignored, every store starts empty
+    fn new(_path: &str) -> Self {
+        Self {
+            map: Arc::new(RwLock::new(Arc::new(Map::new()))),
+        }
+    }
+
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self.map.read().unwrap().get(key).cloned())
+    }
+
+    fn multi_get(&self, keys: &[&[u8]]) -> Result<Vec<Option<Vec<u8>>>, Error> {
+        let map = self.map.read().unwrap();
+        Ok(keys.iter().map(|key| map.get(*key).cloned()).collect())
+    }
+
+    fn exists(&self, key: &[u8]) -> Result<bool, Error> {
+        Ok(self.map.read().unwrap().contains_key(key))
+    }
+
+    fn scan(&self, start: &[u8], count: usize) -> Result<Vec<KeyValue>, Error> {
+        Ok(scan(&self.map.read().unwrap(), start, count))
+    }
+
+    fn batch(&self) -> Result<Self::Batch, Error> {
+        Ok(Self::Batch {
+            map: Arc::clone(&self.map),
+            writes: Vec::new(),
+        })
+    }
+
+    fn bulk_load<I: Iterator<Item = KeyValue>>(&self, pairs: I) -> Result<(), Error> {
+        let mut map = self.map.write().unwrap();
+        Arc::make_mut(&mut map).extend(pairs);
+        Ok(())
+    }
+
+    fn snapshot(&'a self) -> Result<Self::Snapshot, Error> {
+        Ok(MemorySnapshot {
+            map: Arc::clone(&self.map.read().unwrap()),
+        })
+    }
+
+    fn compare_and_swap(
+        &self,
+        key: &[u8],
+        old: Option<&[u8]>,
+        new: Option<&[u8]>,
+    ) -> Result<bool, Error> {
+        let mut map = self.map.write().unwrap();
+        if map.get(key).map(|value| &value[..]) != old {
+            return Ok(false);
+        }
+        let map = Arc::make_mut(&mut map);
+        match new {
+            Some(value) => map.insert(key.to_vec(), value.to_vec()),
+            None => map.remove(key),
+        };
+        Ok(true)
+    }
+
+    fn add(&self, key: &[u8], delta: u64) -> Result<(), Error> {
+        let mut map = self.map.write().unwrap();
+        let map = Arc::make_mut(&mut map);
+        let sum = counter(map.get(key).map(|value| &value[..])).wrapping_add(delta);
+        map.insert(key.to_vec(), sum.to_le_bytes().to_vec());
+        Ok(())
+    }
+}
+
+impl<'a> TransactionalStore<'a> for Store {
+    type Transaction = MemoryTransaction;
+
+    fn transaction(&'a self) -> Result<Self::Transaction, Error> {
+        Ok(MemoryTransaction {
+            map: Arc::clone(&self.map),
+            reads: Vec::new(),
+            writes: BTreeMap::new(),
+        })
+    }
+}
+
+fn scan(map: &Map, start: &[u8], count: usize) -> Vec<KeyValue> {
+    map.range::<[u8], _>((Bound::Included(start), Bound::Unbounded))
+        .take(count)
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect()
+}
+
+enum Write {
+    Put(Vec<u8>, Vec<u8>),
+    Delete(Vec<u8>),
+    DeleteRange(Vec<u8>, Vec<u8>),
+}
+
+pub struct MemoryBatch {
+    map: Arc<RwLock<Arc<Map>>>,
+    writes: Vec<Write>,
+}
+
+impl Batch for MemoryBatch {
+    fn put(&mut self, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        self.writes.push(Write::Put(key.to_vec(), value.to_vec()));
+        Ok(())
+    }
+
+    fn delete(&mut self, key: &[u8]) -> Result<(), Error> {
+        self.writes.push(Write::Delete(key.to_vec()));
+        Ok(())
+    }
+
+    fn delete_range(&mut self, start: &[u8], end: &[u8]) -> Result<(), Error> {
+        self.writes
+            .push(Write::DeleteRange(start.to_vec(), end.to_vec()));
+        Ok(())
+    }
+
+    fn commit(self) -> Result<(), Error> {
+        let mut map = self.map.write().unwrap();
+        let map = Arc::make_mut(&mut map);
+        for write in self.writes {
+            match write {
+                Write::Put(key, value) => {
+                    map.insert(key, value);
+                }
+                Write::Delete(key) => {
+                    map.remove(&key);
+                }
+                Write::DeleteRange(start, end) => {
+                    let mut tail = map.split_off(&start);
+                    let mut rest = tail.split_off(&end);
+                    map.append(&mut rest);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// the map as it was when the snapshot was taken
+pub struct MemorySnapshot {
+    map: Arc<Map>,
+}
+
+impl Snapshot for MemorySnapshot {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self.map.get(key).cloned())
+    }
+
+    fn multi_get(&self, keys: &[&[u8]]) -> Result<Vec<Option<Vec<u8>>>, Error> {
+        Ok(keys.iter().map(|key| self.map.get(*key).cloned()).collect())
+    }
+
+    fn exists(&self, key: &[u8]) -> Result<bool, Error> {
+        Ok(self.map.contains_key(key))
+    }
+}
+
+// the writes are buffered and the reads are checked again under the lock when committing
+pub struct MemoryTransaction {
+    map: Arc<RwLock<Arc<Map>>>,
+    reads: Vec<(Vec<u8>, Option<Vec<u8>>)>,
+    // None for a delete
+    writes: BTreeMap<Vec<u8>, Option<Vec<u8>>>,
+}
+
+impl Transaction for MemoryTransaction {
+    fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        if let Some(value) = self.writes.get(key) {
+            return Ok(value.clone());
+        }
+        let value = self.map.read().unwrap().get(key).cloned();
+        self.reads.push((key.to_vec(), value.clone()));
+        Ok(value)
+    }
+
+    fn put(&mut self, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        self.writes.insert(key.to_vec(), Some(value.to_vec()));
+        Ok(())
+    }
+
+    fn delete(&mut self, key: &[u8]) -> Result<(), Error> {
+        self.writes.insert(key.to_vec(), None);
+        Ok(())
+    }
+
+    fn commit(self) -> Result<(), Error> {
+        let mut map = self.map.write().unwrap();
+        if self
+            .reads
+            .iter()
+            .any(|(key, value)| map.get(key) != value.as_ref())
+        {
+            return Err(Error::ConflictError(
+                "a key read by the transaction was changed".to_string(),
+            ));
+        }
+        let map = Arc::make_mut(&mut map);
+        for (key, value) in self.writes {
+            match value {
+                Some(value) => map.insert(key, value),
+                None => map.remove(&key),
+            };
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conformance::Capabilities;
+
+    // each store is a new map
+    fn capabilities() -> Capabilities {
+        Capabilities {
+            persistent: false,
+            ..Capabilities::default()
+        }
+    }
+
+    crate::conformance_tests!(Store, capabilities());
+    crate::conformance::model_tests!(Store, capabilities());
+
+    #[test]
+    fn transaction_conflict() {
+        let store = Store::new("");
+        let mut first = store.transaction().unwrap();
+        let mut second = store.transaction().unwrap();
+        assert_eq!(None, first.get(&[0, 0]).unwrap());
+        assert_eq!(None, second.get(&[0, 0]).unwrap());
+        second.put(&[0, 0], &[2, 2, 2]).unwrap();
+        second.commit().unwrap();
+        first.put(&[0, 0], &[1, 1, 1]).unwrap();
+        match first.commit() {
+            Err(Error::ConflictError(_)) => {}
+            result => panic!("expected a conflict, got {:?}", result),
+        }
+        assert_eq!(Some(vec![2, 2, 2]), store.get(&[0, 0]).unwrap());
+    }
+}
@@ -0,0 +1,335 @@
+use crate::buffered::Writes;
+use crate::{counter, parse_options, Batch, Error, KeyValue, KeyValueStore, Snapshot};
+use parity_db::{ColId, ColumnOptions, CompressionType, Db, Options};
+use serde::Deserialize;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+// the trait has a single key space, stored in one column
+const COLUMN: ColId = 0;
+// bytes of keys and values in each commit of a bulk load
+const BULK_LOAD_COMMIT_SIZE: usize = 64 << 20;
+
+/// Options of `--options`, e.g. `{"index":"hash","compression":"lz4","sync":true}`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ParityDbOptions {
+    index: Index,
+    compression: Compression,
+    // fsyncs the log before a commit is applied and the data before the log is dropped
+    sync: bool,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Index {
+    // keys in order, for the scans and the range deletes
+    #[default]
+    Btree,
+    // keys by hash, faster but the scans and the range deletes fail
+    Hash,
+}
+
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Compression {
+    #[default]
+    None,
+    Lz4,
+    Snappy,
+}
+
+impl From<Compression> for CompressionType {
+    fn from(compression: Compression) -> CompressionType {
+        match compression {
+            Compression::None => CompressionType::NoCompression,
+            Compression::Lz4 => CompressionType::Lz4,
+            Compression::Snappy => CompressionType::Snappy,
+        }
+    }
+}
+
+pub struct Store {
+    db: Arc<Db>,
+    index: Index,
+    swap_lock: Mutex<()>,
+}
+
+impl<'a> KeyValueStore<'a> for Store {
+    type Batch = ParityDbBatch;
+    type Snapshot = ParityDbSnapshot;
+
+    fn new(path: &str) -> Self {
+        Self::open(path, &serde_json::Value::Null).expect("Failed to open parity-db")
+    }
+
+    // the column options are written in the metadata of a new database, reopening it
+    // with other options fails
+    fn open(path: &str, options: &serde_json::Value) -> Result<Self, Error> {
+        let options: ParityDbOptions = parse_options(options)?;
+        let mut db_options = Options::with_columns(Path::new(path), 1);
+        db_options.sync_wal = options.sync;
+        db_options.sync_data = options.sync;
+        db_options.stats = false;
+        db_options.columns[COLUMN as usize] = ColumnOptions {
+            btree_index: options.index == Index::Btree,
+            compression: options.compression.into(),
+            ..ColumnOptions::default()
+        };
+        Ok(Self {
+            db: Arc::new(Db::open_or_create(&db_options)?),
+            index: options.index,
+            swap_lock: Mutex::new(()),
+        })
+    }
+
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self.db.get(COLUMN, key)?)
+    }
+
+    // parity-db has no batched read
+    fn multi_get(&self, keys: &[&[u8]]) -> Result<Vec<Option<Vec<u8>>>, Error> {
+        keys.iter().map(|key| self.get(key)).collect()
+    }
+
+    // reads the size of the value without reading the value
+    fn exists(&self, key: &[u8]) -> Result<bool, Error> {
+        Ok(self.db.get_size(COLUMN, key)?.is_some())
+    }
+
+    fn scan(&self, start: &[u8], count: usize) -> Result<Vec<KeyValue>, Error> {
+        self.check_ordered("scan")?;
+        let mut iter = self.db.iter(COLUMN)?;
+        iter.seek(start)?;
+        let mut pairs = Vec::new();
+        while pairs.len() < count {
+            match iter.next()? {
+                Some(pair) => pairs.push(pair),
+                None => break,
+            }
+        }
+        Ok(pairs)
+    }
+
+    fn batch(&self) -> Result<Self::Batch, Error> {
+        Ok(ParityDbBatch {
+            db: Arc::clone(&self.db),
+            index: self.index,
+            changes: Vec::new(),
+            ranged: None,
+        })
+    }
+
+    // parity-db has no import of sorted pairs, they are committed by large commits
+    fn bulk_load<I: Iterator<Item = KeyValue>>(&self, pairs: I) -> Result<(), Error> {
+        let mut changes = Vec::new();
+        let mut size = 0;
+        for (key, value) in pairs {
+            size += key.len() + value.len();
+            changes.push((COLUMN, key, Some(value)));
+            if size >= BULK_LOAD_COMMIT_SIZE {
+                self.db.commit(std::mem::take(&mut changes))?;
+                size = 0;
+            }
+        }
+        if !changes.is_empty() {
+            self.db.commit(changes)?;
+        }
+        Ok(())
+    }
+
+    // parity-db has no point-in-time view, reading the latest state wouldn't measure a snapshot
+    fn snapshot(&'a self) -> Result<Self::Snapshot, Error> {
+        Err(Error::DBError("parity-db has no snapshots".to_string()))
+    }
+
+    // atomic with respect to the other compare and swaps and adds of this store, not to
+    // the batches
+    fn compare_and_swap(
+        &self,
+        key: &[u8],
+        old: Option<&[u8]>,
+        new: Option<&[u8]>,
+    ) -> Result<bool, Error> {
+        let _guard = self.swap_lock.lock().unwrap();
+        if self.db.get(COLUMN, key)?.as_deref() != old {
+            return Ok(false);
+        }
+        self.db
+            .commit(vec![(COLUMN, key, new.map(|value| value.to_vec()))])?;
+        Ok(true)
+    }
+
+    // parity-db has no merge, the counter is read and written under the lock of the swaps
+    fn add(&self, key: &[u8], delta: u64) -> Result<(), Error> {
+        let _guard = self.swap_lock.lock().unwrap();
+        let sum = counter(self.db.get(COLUMN, key)?.as_deref()).wrapping_add(delta);
+        self.db
+            .commit(vec![(COLUMN, key, Some(sum.to_le_bytes().to_vec()))])?;
+        Ok(())
+    }
+}
+
+impl Store {
+    fn check_ordered(&self, operation: &str) -> Result<(), Error> {
+        check_ordered(self.index, operation)
+    }
+}
+
+fn check_ordered(index: Index, operation: &str) -> Result<(), Error> {
+    match index {
+        Index::Btree => Ok(()),
+        Index::Hash => Err(Error::DBError(format!(
+            "a parity-db hash column has no key order for a {}",
+            operation
+        ))),
+    }
+}
+
+pub struct ParityDbBatch {
+    db: Arc<Db>,
+    index: Index,
+    // None for a delete
+    changes: Vec<(ColId, Vec<u8>, Option<Vec<u8>>)>,
+    // the writes from the first range delete
+    ranged: Option<Writes>,
+}
+
+impl Batch for ParityDbBatch {
+    fn put(&mut self, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        match &mut self.ranged {
+            Some(writes) => writes.put(key, value),
+            None => self
+                .changes
+                .push((COLUMN, key.to_vec(), Some(value.to_vec()))),
+        }
+        Ok(())
+    }
+
+    fn delete(&mut self, key: &[u8]) -> Result<(), Error> {
+        match &mut self.ranged {
+            Some(writes) => writes.delete(key),
+            None => self.changes.push((COLUMN, key.to_vec(), None)),
+        }
+        Ok(())
+    }
+
+    fn delete_range(&mut self, start: &[u8], end: &[u8]) -> Result<(), Error> {
+        check_ordered(self.index, "range delete")?;
+        for (_, key, value) in &mut self.changes {
+            if start <= &key[..] && &key[..] < end {
+                *value = None;
+            }
+        }
+        self.ranged
+            .get_or_insert_with(Writes::default)
+            .delete_range(start, end);
+        Ok(())
+    }
+
+    // parity-db has no range delete, the keys of a range are deleted one by one. The commit
+    // is queued and applied by a background thread, the reads see it as soon as it returns
+    fn commit(self) -> Result<(), Error> {
+        let mut changes = self.changes;
+        if let Some(writes) = self.ranged {
+            let db = &self.db;
+            let ranged = writes.changes(|start, end| {
+                let mut keys = Vec::new();
+                let mut iter = db.iter(COLUMN)?;
+                iter.seek(start)?;
+                while let Some((key, _)) = iter.next()? {
+                    if key.as_slice() >= end {
+                        break;
+                    }
+                    keys.push(key);
+                }
+                Ok(keys)
+            })?;
+            changes.retain(|(_, key, _)| !ranged.contains_key(key));
+            changes.extend(ranged.into_iter().map(|(key, value)| (COLUMN, key, value)));
+        }
+        self.db.commit(changes)?;
+        Ok(())
+    }
+}
+
+/// Never built, `Store::snapshot` always fails.
+pub enum ParityDbSnapshot {}
+
+impl Snapshot for ParityDbSnapshot {
+    fn get(&self, _key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        match *self {}
+    }
+
+    fn multi_get(&self, _keys: &[&[u8]]) -> Result<Vec<Option<Vec<u8>>>, Error> {
+        match *self {}
+    }
+
+    fn exists(&self, _key: &[u8]) -> Result<bool, Error> {
+        match *self {}
+    }
+}
+
+impl From<parity_db::Error> for Error {
+    fn from(e: parity_db::Error) -> Error {
+        Error::DBError(e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conformance::Capabilities;
+    use tempfile;
+
+    fn capabilities() -> Capabilities {
+        Capabilities {
+            snapshots: false,
+            ..Capabilities::default()
+        }
+    }
+
+    crate::conformance_tests!(Store, capabilities());
+    crate::conformance::model_tests!(Store, capabilities());
+
+    // the checks of a hash column, without the model which scans the store after each step
+    mod hash {
+        use super::{capabilities, Store};
+        use crate::conformance::Capabilities;
+        use crate::KeyValueStore;
+
+        fn open_hash(path: &str) -> impl Fn() -> Store {
+            let path = path.to_string();
+            move || Store::open(&path, &serde_json::json!({"index": "hash"})).unwrap()
+        }
+
+        crate::conformance_tests!(
+            Store,
+            open_hash,
+            Capabilities {
+                ordered: false,
+                ..capabilities()
+            }
+        );
+    }
+
+    #[test]
+    fn options() {
+        let tmp_dir = tempfile::Builder::new()
+            .prefix("options")
+            .tempdir()
+            .unwrap();
+        let path = tmp_dir.path().to_str().unwrap();
+        assert!(Store::open(path, &serde_json::json!({"index": "lsm"})).is_err());
+        let options = serde_json::json!({"index": "hash", "compression": "lz4", "sync": true});
+        let store = Store::open(path, &options).unwrap();
+        let mut batch = store.batch().unwrap();
+        batch.put(&[0, 0], &[0, 0, 0]).unwrap();
+        batch.commit().unwrap();
+        assert!(store.scan(&[], 1).is_err());
+        drop(store);
+
+        let store = Store::open(path, &options).unwrap();
+        assert_eq!(Some(vec![0, 0, 0]), store.get(&[0, 0]).unwrap());
+    }
+}
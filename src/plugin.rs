@@ -0,0 +1,340 @@
+use crate::dynamic::DynStore;
+use crate::remote;
+use crate::{Error, KeyValueStore};
+use std::os::raw::c_void;
+use std::panic::{self, AssertUnwindSafe};
+use std::{mem, ptr, slice};
+
+/// Version of `PluginApi`, a plugin built for another version, or for other frames of
+/// `remote`, isn't loaded.
+pub const ABI_VERSION: u32 = 2;
+
+/// Name of the function of a plugin which returns its `PluginApi`.
+pub const SYMBOL: &[u8] = b"rkb_plugin";
+
+/// Bytes allocated by a plugin, which are freed by its `free`.
+#[repr(C)]
+pub struct Buffer {
+    pub ptr: *mut u8,
+    pub len: usize,
+    pub capacity: usize,
+}
+
+/// The functions of a plugin, returned by its only symbol `rkb_plugin`. The requests and the
+/// responses are the frames of `remote::serve`, so the C ABI only passes bytes, and the plugin
+/// can be built by another version of rustc. The store is an opaque pointer from `open` to
+/// `close`.
+#[repr(C)]
+pub struct PluginApi {
+    pub abi_version: u32,
+    // the `remote::PROTOCOL_VERSION` of the frames of `call`
+    pub protocol_version: u32,
+    // opens the store at the path with its options as json, both utf-8, returns null and
+    // sets `error` to the message when it fails
+    pub open: unsafe extern "C" fn(
+        path: *const u8,
+        path_len: usize,
+        options: *const u8,
+        options_len: usize,
+        error: *mut Buffer,
+    ) -> *mut c_void,
+    // returns the response to the frame of a request, as `remote::serve` does
+    pub call: unsafe extern "C" fn(store: *mut c_void, request: *const u8, len: usize) -> Buffer,
+    pub free: unsafe extern "C" fn(buffer: Buffer),
+    pub close: unsafe extern "C" fn(store: *mut c_void),
+}
+
+/// Exports the store `$store`, a `KeyValueStore`, as the plugin of a crate with
+/// `crate-type = ["cdylib"]`, which the `plugin` backend loads at run time, e.g.
+///
+/// ```ignore
+/// rust_kvstore_bench::export_plugin!(my_store::Store);
+/// ```
+#[macro_export]
+macro_rules! export_plugin {
+    ($store:ty) => {
+        #[no_mangle]
+        pub extern "C" fn rkb_plugin() -> $crate::plugin::PluginApi {
+            $crate::plugin::api::<$store>()
+        }
+    };
+}
+
+/// The `PluginApi` of the store `T`, returned by the `rkb_plugin` of `export_plugin!`.
+pub fn api<T>() -> PluginApi
+where
+    T: for<'a> KeyValueStore<'a> + Sync + 'static,
+{
+    PluginApi {
+        abi_version: ABI_VERSION,
+        protocol_version: remote::PROTOCOL_VERSION,
+        open: open::<T>,
+        call,
+        free,
+        close,
+    }
+}
+
+// the store is a boxed `Box<dyn DynStore>`, so that the pointer is thin
+unsafe extern "C" fn open<T>(
+    path: *const u8,
+    path_len: usize,
+    options: *const u8,
+    options_len: usize,
+    error: *mut Buffer,
+) -> *mut c_void
+where
+    T: for<'a> KeyValueStore<'a> + Sync + 'static,
+{
+    let path = String::from_utf8_lossy(slice::from_raw_parts(path, path_len)).into_owned();
+    let options = slice::from_raw_parts(options, options_len);
+    let opened = panic::catch_unwind(|| -> Result<Box<dyn DynStore>, Error> {
+        let options = serde_json::from_slice(options)?;
+        Ok(Box::new(T::open(&path, &options)?))
+    });
+    match opened {
+        Ok(Ok(store)) => Box::into_raw(Box::new(store)) as *mut c_void,
+        Ok(Err(e)) => {
+            *error = buffer(format!("{:?}", e).into_bytes());
+            ptr::null_mut()
+        }
+        Err(_) => {
+            *error = buffer(b"the store panicked while opening".to_vec());
+            ptr::null_mut()
+        }
+    }
+}
+
+unsafe extern "C" fn call(store: *mut c_void, request: *const u8, len: usize) -> Buffer {
+    let store = &**(store as *const Box<dyn DynStore>);
+    let request = slice::from_raw_parts(request, len);
+    // a panic of the store must not unwind through the C ABI
+    buffer(
+        panic::catch_unwind(AssertUnwindSafe(|| remote::respond(store, request))).unwrap_or_else(
+            |_| {
+                remote::respond_error(Error::DBError(
+                    "the store panicked while serving a request".to_string(),
+                ))
+            },
+        ),
+    )
+}
+
+unsafe extern "C" fn free(buffer: Buffer) {
+    drop(Vec::from_raw_parts(buffer.ptr, buffer.len, buffer.capacity));
+}
+
+unsafe extern "C" fn close(store: *mut c_void) {
+    drop(Box::from_raw(store as *mut Box<dyn DynStore>));
+}
+
+fn buffer(bytes: Vec<u8>) -> Buffer {
+    let mut bytes = mem::ManuallyDrop::new(bytes);
+    Buffer {
+        ptr: bytes.as_mut_ptr(),
+        len: bytes.len(),
+        capacity: bytes.capacity(),
+    }
+}
+
+#[cfg(feature = "plugin")]
+pub use self::loader::{Options, Plugin, Store};
+
+#[cfg(feature = "plugin")]
+mod loader {
+    use super::*;
+    use crate::parse_options;
+    use crate::remote::{Client, Transport};
+    use libloading::Library;
+    use serde::Deserialize;
+
+    /// A store of a plugin, e.g. `{"library":"./libmy_store.so","options":{}}` as the
+    /// options of the `plugin` backend. Each request is encoded as the frame of
+    /// `remote::Store`, which costs a copy of its keys and values.
+    pub type Store = Client<Plugin>;
+
+    /// Options of `--options` of the `plugin` backend.
+    #[derive(Debug, Default, Deserialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct Options {
+        // path of the cdylib
+        library: String,
+        // options of the store of the plugin
+        options: serde_json::Value,
+    }
+
+    /// A plugin loaded with the store it opened, which is closed before unloading it.
+    pub struct Plugin {
+        api: PluginApi,
+        store: *mut c_void,
+        // None when the api is linked in, in the tests
+        _library: Option<Library>,
+    }
+
+    // the store of a plugin is a `DynStore`, which is Sync, and `call` only borrows it
+    unsafe impl Send for Plugin {}
+    unsafe impl Sync for Plugin {}
+
+    impl Plugin {
+        fn open_api(
+            api: PluginApi,
+            library: Option<Library>,
+            path: &str,
+            options: &serde_json::Value,
+        ) -> Result<Self, Error> {
+            if api.abi_version != ABI_VERSION {
+                return Err(Error::DBError(format!(
+                    "the plugin has the ABI version {}, not {}",
+                    api.abi_version, ABI_VERSION
+                )));
+            }
+            if api.protocol_version != remote::PROTOCOL_VERSION {
+                return Err(Error::DBError(format!(
+                    "the plugin has the protocol version {}, not {}",
+                    api.protocol_version,
+                    remote::PROTOCOL_VERSION
+                )));
+            }
+            let options = serde_json::to_vec(options)?;
+            let mut error = Buffer {
+                ptr: ptr::null_mut(),
+                len: 0,
+                capacity: 0,
+            };
+            let store = unsafe {
+                (api.open)(
+                    path.as_ptr(),
+                    path.len(),
+                    options.as_ptr(),
+                    options.len(),
+                    &mut error,
+                )
+            };
+            if store.is_null() {
+                let message = String::from_utf8_lossy(&take(&api, error)).into_owned();
+                return Err(Error::DBError(format!(
+                    "the plugin failed to open: {}",
+                    message
+                )));
+            }
+            Ok(Self {
+                api,
+                store,
+                _library: library,
+            })
+        }
+    }
+
+    impl Transport for Plugin {
+        fn open(path: &str, options: &serde_json::Value) -> Result<Self, Error> {
+            let options: Options = parse_options(options)?;
+            if options.library.is_empty() {
+                return Err(Error::WorkloadError(
+                    "the plugin store needs the path of its library".to_string(),
+                ));
+            }
+            let library = unsafe { Library::new(&options.library) }.map_err(|e| {
+                Error::DBError(format!("failed to load {}: {}", options.library, e))
+            })?;
+            let api = unsafe {
+                let entry = library
+                    .get::<unsafe extern "C" fn() -> PluginApi>(SYMBOL)
+                    .map_err(|e| {
+                        Error::DBError(format!("{} is not a plugin: {}", options.library, e))
+                    })?;
+                entry()
+            };
+            Self::open_api(api, Some(library), path, &options.options)
+        }
+
+        fn call(&self, request: &[u8]) -> Result<Vec<u8>, Error> {
+            let response = unsafe { (self.api.call)(self.store, request.as_ptr(), request.len()) };
+            Ok(take(&self.api, response))
+        }
+    }
+
+    impl Drop for Plugin {
+        fn drop(&mut self) {
+            unsafe { (self.api.close)(self.store) }
+        }
+    }
+
+    // copies the bytes of the plugin, and lets it free them
+    fn take(api: &PluginApi, buffer: Buffer) -> Vec<u8> {
+        if buffer.ptr.is_null() {
+            return Vec::new();
+        }
+        let bytes = unsafe { slice::from_raw_parts(buffer.ptr, buffer.len) }.to_vec();
+        unsafe { (api.free)(buffer) };
+        bytes
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::{api, Client, Plugin, Store};
+        use crate::conformance::Capabilities;
+        use crate::memory::Store as Memory;
+        use crate::remote::Transport;
+        use crate::{counter, Batch, Error, KeyValueStore};
+
+        fn linked(options: &serde_json::Value) -> Result<Store, Error> {
+            let plugin = Plugin::open_api(api::<Memory>(), None, "", options)?;
+            Ok(Client::from_transport(plugin))
+        }
+
+        // each store is a new in-memory store of the plugin
+        fn open_linked(_: &str) -> impl Fn() -> Store {
+            || linked(&serde_json::Value::Null).unwrap()
+        }
+
+        fn capabilities() -> Capabilities {
+            Capabilities {
+                persistent: false,
+                snapshots: false,
+                ..Capabilities::default()
+            }
+        }
+
+        crate::conformance_tests!(Store, open_linked, capabilities());
+        crate::conformance::model_tests!(Store, open_linked, capabilities());
+
+        #[test]
+        fn call_through_api() {
+            let store = linked(&serde_json::Value::Null).unwrap();
+            let mut batch = store.batch().unwrap();
+            batch.put(&[0, 0], &[0, 0, 0]).unwrap();
+            batch.put(&[1, 1], &[1, 1, 1]).unwrap();
+            batch.commit().unwrap();
+
+            assert_eq!(Some(vec![0, 0, 0]), store.get(&[0, 0]).unwrap());
+            assert!(!store.exists(&[2, 2]).unwrap());
+            assert_eq!(
+                vec![(vec![1, 1], vec![1, 1, 1])],
+                store.scan(&[1], 10).unwrap()
+            );
+            store.add(&[2], 2).unwrap();
+            assert_eq!(2, counter(store.get(&[2]).unwrap().as_deref()));
+        }
+
+        #[test]
+        fn open_errors() {
+            // the memory store has no options
+            match linked(&serde_json::json!({"cache_size": 1})) {
+                Err(Error::DBError(message)) => assert!(message.starts_with(
+                    "the plugin failed to open: WorkloadError(\"the store has no options"
+                )),
+                _ => panic!("the plugin opened"),
+            }
+            assert!(Plugin::open(
+                "",
+                &serde_json::json!({"library": "/nonexistent/libplugin.so"})
+            )
+            .is_err());
+            assert!(Plugin::open("", &serde_json::Value::Null).is_err());
+
+            let mut other = api::<Memory>();
+            other.protocol_version += 1;
+            assert!(Plugin::open_api(other, None, "", &serde_json::Value::Null).is_err());
+        }
+    }
+}
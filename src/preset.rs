@@ -0,0 +1,90 @@
+use crate::Error;
+use serde::Deserialize;
+
+// the memtables of a store, the one being written and one being flushed, before writes stall
+pub const MEMTABLES: u64 = 2;
+
+/// Options of `--options` shared by the LSM stores, `rocksdb` and `fjall`, which configure
+/// them with the same settings so that their results can be compared, e.g.
+/// `{"memtable_size":67108864,"compression":"lz4","bloom_bits_per_key":10,"block_size":4096}`.
+/// The missing fields take the defaults below, and a store which can't apply one of the
+/// settings exactly fails to open with all the settings it can't match.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LsmPreset {
+    // bytes of a memtable before it's flushed to a table file
+    pub memtable_size: u64,
+    pub compression: Compression,
+    // bits per key of the bloom filters of the table files, null without filters
+    pub bloom_bits_per_key: Option<u8>,
+    // bytes of the data blocks of the table files, before compression
+    pub block_size: u64,
+}
+
+impl Default for LsmPreset {
+    fn default() -> Self {
+        Self {
+            memtable_size: 64 << 20,
+            compression: Compression::default(),
+            bloom_bits_per_key: Some(10),
+            block_size: 4 << 10,
+        }
+    }
+}
+
+// the same compression on all the levels
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Compression {
+    None,
+    #[default]
+    Lz4,
+    // at the default level of zlib, 6
+    Zlib,
+    Snappy,
+    Zstd,
+}
+
+/// Fails with the settings of the preset which `store` can't apply exactly, for the stores
+/// which only take some values of a setting.
+pub fn check_matched(store: &str, unmatched: Vec<String>) -> Result<(), Error> {
+    if unmatched.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::WorkloadError(format!(
+            "{} can't match the preset: {}",
+            store,
+            unmatched.join(", ")
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_options;
+    use serde_json::json;
+
+    #[test]
+    fn defaults() {
+        let preset: LsmPreset = parse_options(&json!({"compression": "zstd"})).unwrap();
+        assert_eq!(preset.memtable_size, 64 << 20);
+        assert_eq!(preset.compression, Compression::Zstd);
+        assert_eq!(preset.bloom_bits_per_key, Some(10));
+
+        let preset: LsmPreset = parse_options(&json!({"bloom_bits_per_key": null})).unwrap();
+        assert_eq!(preset.bloom_bits_per_key, None);
+        assert!(parse_options::<LsmPreset>(&json!({"memtables": 2})).is_err());
+    }
+
+    #[test]
+    fn check() {
+        assert!(check_matched("fjall", Vec::new()).is_ok());
+        match check_matched("fjall", vec!["compression snappy".to_string()]) {
+            Err(Error::WorkloadError(message)) => {
+                assert_eq!(message, "fjall can't match the preset: compression snappy")
+            }
+            _ => panic!("the preset is matched"),
+        }
+    }
+}
@@ -0,0 +1,321 @@
+use crate::{
+    counter, parse_options, Batch, Error, KeyValue, KeyValueStore, Snapshot, Transaction,
+    TransactionalStore,
+};
+use redb::{Database, Durability, ReadOnlyTable, ReadableTable, TableDefinition, WriteTransaction};
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+const TABLE: TableDefinition<&[u8], &[u8]> = TableDefinition::new("redb");
+// bytes of keys and values put by each write transaction of a bulk load
+const BULK_LOAD_TRANSACTION_SIZE: usize = 64 << 20;
+
+/// Options of `--options`, e.g. `{"durability":"immediate","cache_size":1073741824}`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RedbOptions {
+    durability: RedbDurability,
+    // bytes of the page cache, redb's default when missing
+    cache_size: Option<usize>,
+}
+
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum RedbDurability {
+    // the commits are only persisted by a later durable commit
+    None,
+    // the commits are not fsynced
+    #[default]
+    Eventual,
+    Immediate,
+}
+
+impl From<RedbDurability> for Durability {
+    fn from(durability: RedbDurability) -> Durability {
+        match durability {
+            RedbDurability::None => Durability::None,
+            RedbDurability::Eventual => Durability::Eventual,
+            RedbDurability::Immediate => Durability::Immediate,
+        }
+    }
+}
+
+pub struct Store {
+    db: Arc<Database>,
+    durability: Durability,
+}
+
+impl Store {
+    fn begin_write(&self) -> Result<WriteTransaction, Error> {
+        let mut tx = self.db.begin_write()?;
+        tx.set_durability(self.durability);
+        Ok(tx)
+    }
+}
+
+impl<'a> KeyValueStore<'a> for Store {
+    type Batch = RedbBatch;
+    type Snapshot = RedbSnapshot;
+
+    fn new(path: &str) -> Self {
+        Self::open(path, &serde_json::Value::Null).expect("Failed to open redb")
+    }
+
+    // redb stores the database in one file, in the directory of the path
+    fn open(path: &str, options: &serde_json::Value) -> Result<Self, Error> {
+        let options: RedbOptions = parse_options(options)?;
+        fs::create_dir_all(path)?;
+        let mut builder = Database::builder();
+        if let Some(cache_size) = options.cache_size {
+            builder.set_cache_size(cache_size);
+        }
+        let db = builder.create(Path::new(path).join("data.redb"))?;
+        // a read transaction can't open a table which was never written
+        let tx = db.begin_write()?;
+        tx.open_table(TABLE)?;
+        tx.commit()?;
+        Ok(Self {
+            db: Arc::new(db),
+            durability: options.durability.into(),
+        })
+    }
+
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        get(&self.db.begin_read()?.open_table(TABLE)?, key)
+    }
+
+    // all the keys are read by one transaction instead of one per key
+    fn multi_get(&self, keys: &[&[u8]]) -> Result<Vec<Option<Vec<u8>>>, Error> {
+        let table = self.db.begin_read()?.open_table(TABLE)?;
+        keys.iter().map(|key| get(&table, key)).collect()
+    }
+
+    fn exists(&self, key: &[u8]) -> Result<bool, Error> {
+        let table = self.db.begin_read()?.open_table(TABLE)?;
+        Ok(table.get(key)?.is_some())
+    }
+
+    fn scan(&self, start: &[u8], count: usize) -> Result<Vec<KeyValue>, Error> {
+        scan(&self.db.begin_read()?.open_table(TABLE)?, start, count)
+    }
+
+    fn batch(&self) -> Result<Self::Batch, Error> {
+        Ok(RedbBatch {
+            tx: self.begin_write()?,
+        })
+    }
+
+    fn bulk_load<I: Iterator<Item = KeyValue>>(&self, pairs: I) -> Result<(), Error> {
+        let mut pairs = pairs.peekable();
+        while pairs.peek().is_some() {
+            let tx = self.begin_write()?;
+            {
+                let mut table = tx.open_table(TABLE)?;
+                let mut size = 0;
+                for (key, value) in pairs.by_ref() {
+                    table.insert(&key[..], &value[..])?;
+                    size += key.len() + value.len();
+                    if size >= BULK_LOAD_TRANSACTION_SIZE {
+                        break;
+                    }
+                }
+            }
+            tx.commit()?;
+        }
+        Ok(())
+    }
+
+    // keeps one read transaction open, the pages it sees can't be freed by the writers
+    fn snapshot(&'a self) -> Result<Self::Snapshot, Error> {
+        Ok(RedbSnapshot {
+            table: self.db.begin_read()?.open_table(TABLE)?,
+        })
+    }
+
+    fn compare_and_swap(
+        &self,
+        key: &[u8],
+        old: Option<&[u8]>,
+        new: Option<&[u8]>,
+    ) -> Result<bool, Error> {
+        let tx = self.begin_write()?;
+        {
+            let mut table = tx.open_table(TABLE)?;
+            if get(&table, key)?.as_deref() != old {
+                return Ok(false);
+            }
+            match new {
+                Some(value) => table.insert(key, value)?,
+                None => table.remove(key)?,
+            };
+        }
+        tx.commit()?;
+        Ok(true)
+    }
+
+    fn add(&self, key: &[u8], delta: u64) -> Result<(), Error> {
+        let tx = self.begin_write()?;
+        {
+            let mut table = tx.open_table(TABLE)?;
+            let sum = counter(get(&table, key)?.as_deref()).wrapping_add(delta);
+            table.insert(key, &sum.to_le_bytes()[..])?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+impl<'a> TransactionalStore<'a> for Store {
+    type Transaction = RedbTransaction;
+
+    fn transaction(&'a self) -> Result<Self::Transaction, Error> {
+        Ok(RedbTransaction {
+            tx: self.begin_write()?,
+        })
+    }
+}
+
+fn get<T: ReadableTable<&'static [u8], &'static [u8]>>(
+    table: &T,
+    key: &[u8],
+) -> Result<Option<Vec<u8>>, Error> {
+    Ok(table.get(key)?.map(|value| value.value().to_vec()))
+}
+
+fn scan<T: ReadableTable<&'static [u8], &'static [u8]>>(
+    table: &T,
+    start: &[u8],
+    count: usize,
+) -> Result<Vec<KeyValue>, Error> {
+    table
+        .range(start..)?
+        .take(count)
+        .map(|item| {
+            let (key, value) = item?;
+            Ok((key.value().to_vec(), value.value().to_vec()))
+        })
+        .collect()
+}
+
+pub struct RedbBatch {
+    tx: WriteTransaction,
+}
+
+impl Batch for RedbBatch {
+    fn put(&mut self, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        self.tx.open_table(TABLE)?.insert(key, value)?;
+        Ok(())
+    }
+
+    fn delete(&mut self, key: &[u8]) -> Result<(), Error> {
+        self.tx.open_table(TABLE)?.remove(key)?;
+        Ok(())
+    }
+
+    fn delete_range(&mut self, start: &[u8], end: &[u8]) -> Result<(), Error> {
+        self.tx
+            .open_table(TABLE)?
+            .retain_in(start..end, |_, _| false)?;
+        Ok(())
+    }
+
+    fn commit(self) -> Result<(), Error> {
+        self.tx.commit()?;
+        Ok(())
+    }
+}
+
+pub struct RedbSnapshot {
+    table: ReadOnlyTable<&'static [u8], &'static [u8]>,
+}
+
+impl Snapshot for RedbSnapshot {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        get(&self.table, key)
+    }
+
+    fn multi_get(&self, keys: &[&[u8]]) -> Result<Vec<Option<Vec<u8>>>, Error> {
+        keys.iter().map(|key| get(&self.table, key)).collect()
+    }
+
+    fn exists(&self, key: &[u8]) -> Result<bool, Error> {
+        Ok(self.table.get(key)?.is_some())
+    }
+}
+
+// the same write transaction as a batch, which also reads
+pub struct RedbTransaction {
+    tx: WriteTransaction,
+}
+
+impl Transaction for RedbTransaction {
+    fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        get(&self.tx.open_table(TABLE)?, key)
+    }
+
+    fn put(&mut self, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        self.tx.open_table(TABLE)?.insert(key, value)?;
+        Ok(())
+    }
+
+    fn delete(&mut self, key: &[u8]) -> Result<(), Error> {
+        self.tx.open_table(TABLE)?.remove(key)?;
+        Ok(())
+    }
+
+    fn commit(self) -> Result<(), Error> {
+        self.tx.commit()?;
+        Ok(())
+    }
+}
+
+// redb has an error type for each operation
+macro_rules! impl_from_redb_error {
+    ($($error:ty),*) => {
+        $(
+            impl From<$error> for Error {
+                fn from(e: $error) -> Error {
+                    Error::DBError(e.to_string())
+                }
+            }
+        )*
+    };
+}
+
+impl_from_redb_error!(
+    redb::DatabaseError,
+    redb::TransactionError,
+    redb::TableError,
+    redb::StorageError,
+    redb::CommitError
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile;
+
+    crate::conformance_tests!(Store);
+    crate::conformance::model_tests!(Store);
+
+    #[test]
+    fn options() {
+        let tmp_dir = tempfile::Builder::new()
+            .prefix("options")
+            .tempdir()
+            .unwrap();
+        let path = tmp_dir.path().to_str().unwrap();
+        assert!(Store::open(path, &serde_json::json!({"sync": true})).is_err());
+        let options = serde_json::json!({"durability": "immediate", "cache_size": 1 << 20});
+        let store = Store::open(path, &options).unwrap();
+        let mut batch = store.batch().unwrap();
+        batch.put(&[0, 0], &[0, 0, 0]).unwrap();
+        batch.commit().unwrap();
+        drop(store);
+
+        let store = Store::new(path);
+        assert_eq!(Some(vec![0, 0, 0]), store.get(&[0, 0]).unwrap());
+    }
+}
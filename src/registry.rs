@@ -0,0 +1,169 @@
+use crate::{Error, KeyValueStore, TransactionalStore};
+
+/// A store compiled into the crate, opened by its name as the `db_type` of the subcommands.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Backend {
+    pub name: &'static str,
+    pub description: &'static str,
+    // whether it's a `TransactionalStore`
+    pub transactional: bool,
+    // whether its data is in the files of its path, which can be copied as a dataset
+    pub persistent: bool,
+}
+
+/// Code generic over the type of a store, which `visit` runs with the store of a backend.
+pub trait Visitor: Sized {
+    type Output;
+
+    fn visit<T>(self, backend: &Backend) -> Self::Output
+    where
+        T: for<'a> KeyValueStore<'a> + Sync;
+
+    // the transactional stores are visited by this one, as any other store by default
+    fn visit_transactional<T>(self, backend: &Backend) -> Self::Output
+    where
+        T: for<'a> TransactionalStore<'a> + Sync,
+    {
+        self.visit::<T>(backend)
+    }
+}
+
+// each backend is listed once, with the feature which compiles it in, and the method of
+// `Visitor` which visits its store
+macro_rules! registry {
+    ($($(#[$cfg:meta])* $name:literal => $visit:ident::<$store:ty>, $persistent:literal, $description:literal;)*) => {
+        /// The backends compiled in by the features of the crate.
+        pub const BACKENDS: &[Backend] = &[
+            $(
+                $(#[$cfg])*
+                Backend {
+                    name: $name,
+                    description: $description,
+                    transactional: registry!(@transactional $visit),
+                    persistent: $persistent,
+                },
+            )*
+        ];
+
+        /// Runs `visitor` with the store of the backend `name`, fails when it isn't compiled in.
+        pub fn visit<V: Visitor>(name: &str, visitor: V) -> Result<V::Output, Error> {
+            let backend = find(name)?;
+            match name {
+                $(
+                    $(#[$cfg])*
+                    $name => Ok(visitor.$visit::<$store>(&backend)),
+                )*
+                _ => Err(unknown(name)),
+            }
+        }
+    };
+    (@transactional visit) => {
+        false
+    };
+    (@transactional visit_transactional) => {
+        true
+    };
+}
+
+registry! {
+    #[cfg(feature = "rocksdb")]
+    "rocksdb" => visit::<crate::rocksdb::Store>, true, "RocksDB without transactions";
+    #[cfg(feature = "rocksdb")]
+    "rocksdb_optimistic" => visit_transactional::<crate::rocksdb::OptimisticStore>, true,
+        "RocksDB with optimistic transactions";
+    #[cfg(feature = "rocksdb")]
+    "rocksdb_pessimistic" => visit_transactional::<crate::rocksdb::PessimisticStore>, true,
+        "RocksDB with pessimistic transactions";
+    #[cfg(feature = "lmdb")]
+    "lmdb" => visit_transactional::<crate::lmdb_zero::Store>, true, "LMDB";
+    #[cfg(feature = "mdbx")]
+    "mdbx" => visit_transactional::<crate::mdbx::Store>, true, "libmdbx";
+    #[cfg(feature = "fjall")]
+    "fjall" => visit_transactional::<crate::fjall::Store>, true, "fjall, an LSM tree in pure Rust";
+    #[cfg(feature = "redb")]
+    "redb" => visit_transactional::<crate::redb::Store>, true, "redb";
+    #[cfg(feature = "sqlite")]
+    "sqlite" => visit_transactional::<crate::sqlite::Store>, true, "SQLite, one table";
+    #[cfg(feature = "parity_db")]
+    "parity_db" => visit::<crate::parity_db::Store>, true, "parity-db, one column";
+    #[cfg(feature = "sled")]
+    "sled" => visit_transactional::<crate::sled::Store>, true, "sled";
+    "memory" => visit_transactional::<crate::memory::Store>, false, "in-memory baseline";
+    "remote" => visit::<crate::remote::Store>, false,
+        "a store of `serve`, the path is its address";
+    #[cfg(feature = "plugin")]
+    "plugin" => visit::<crate::plugin::Store>, false,
+        "a store of a cdylib, its library and its options are the options";
+}
+
+/// The backend `name`, fails with the names of the backends when it isn't compiled in.
+pub fn find(name: &str) -> Result<Backend, Error> {
+    BACKENDS
+        .iter()
+        .find(|backend| backend.name == name)
+        .copied()
+        .ok_or_else(|| unknown(name))
+}
+
+/// The names of the backends compiled in.
+pub fn names() -> Vec<&'static str> {
+    BACKENDS.iter().map(|backend| backend.name).collect()
+}
+
+fn unknown(name: &str) -> Error {
+    Error::WorkloadError(format!(
+        "unknown db_type: {}, the backends compiled in are {}",
+        name,
+        names().join(", ")
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Name;
+
+    impl Visitor for Name {
+        type Output = (&'static str, bool);
+
+        fn visit<T>(self, backend: &Backend) -> Self::Output
+        where
+            T: for<'a> KeyValueStore<'a> + Sync,
+        {
+            (backend.name, false)
+        }
+
+        fn visit_transactional<T>(self, backend: &Backend) -> Self::Output
+        where
+            T: for<'a> TransactionalStore<'a> + Sync,
+        {
+            (backend.name, true)
+        }
+    }
+
+    #[test]
+    fn visit_backends() {
+        for backend in BACKENDS {
+            assert_eq!(
+                (backend.name, backend.transactional),
+                visit(backend.name, Name).unwrap()
+            );
+        }
+        assert_eq!(("memory", true), visit("memory", Name).unwrap());
+        assert_eq!(("remote", false), visit("remote", Name).unwrap());
+    }
+
+    #[test]
+    fn unknown_backend() {
+        match visit("leveldb", Name) {
+            Err(Error::WorkloadError(message)) => {
+                assert!(
+                    message.starts_with("unknown db_type: leveldb, the backends compiled in are ")
+                );
+                assert!(message.contains("memory, remote"));
+            }
+            _ => panic!("leveldb is compiled in"),
+        }
+    }
+}
@@ -0,0 +1,658 @@
+use crate::dynamic::DynStore;
+use crate::format::{read_bytes, read_u32, read_u64, read_u8, write_bytes};
+use crate::{Batch, Error, KeyValue, KeyValueStore, Snapshot};
+use std::fs;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+#[cfg(unix)]
+use std::os::unix::fs::FileTypeExt;
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Version of the frames of the requests and the responses, bumped when one of them changes.
+/// A client of `serve` sends it first, and the server refuses the clients of other versions.
+pub const PROTOCOL_VERSION: u32 = 2;
+
+// each request and each response is a frame, its length as a little-endian u32 followed by
+// its body, the body of a request starts with its tag and the one of a response with its status
+const REQUEST_GET: u8 = 0;
+const REQUEST_EXISTS: u8 = 1;
+const REQUEST_MULTI_GET: u8 = 2;
+const REQUEST_SCAN: u8 = 3;
+const REQUEST_BATCH: u8 = 4;
+const REQUEST_BULK_LOAD: u8 = 5;
+const REQUEST_COMPARE_AND_SWAP: u8 = 6;
+const REQUEST_ADD: u8 = 7;
+
+const OPERATION_PUT: u8 = 0;
+const OPERATION_DELETE: u8 = 1;
+const OPERATION_DELETE_RANGE: u8 = 2;
+
+// the other statuses are the variant of the error, followed by its message
+const STATUS_OK: u8 = 0;
+const STATUS_DB_ERROR: u8 = 1;
+const STATUS_IO_ERROR: u8 = 2;
+const STATUS_WORKLOAD_ERROR: u8 = 3;
+const STATUS_CONFLICT_ERROR: u8 = 4;
+
+// bytes of keys and values sent by each request of a bulk load
+const BULK_LOAD_REQUEST_SIZE: usize = 16 << 20;
+
+/// A store served by `serve` in another process, the path is the address of the server,
+/// a TCP address such as `127.0.0.1:7878`, or else the path of a Unix socket on Unix.
+pub type Store = Client<Connection>;
+
+/// A store which encodes its requests as the frames of the protocol of `serve`, and sends
+/// them by `T`. Each request waits for its response, so the latencies include the
+/// serialization and the round trip.
+pub struct Client<T> {
+    transport: Arc<T>,
+}
+
+/// How a `Client` sends the frame of a request and receives the one of its response.
+pub trait Transport: Sized + Send + Sync + 'static {
+    fn open(path: &str, options: &serde_json::Value) -> Result<Self, Error>;
+    fn call(&self, request: &[u8]) -> Result<Vec<u8>, Error>;
+}
+
+impl<T: Transport> Client<T> {
+    pub(crate) fn from_transport(transport: T) -> Self {
+        Self {
+            transport: Arc::new(transport),
+        }
+    }
+
+    // the body of the response after its status
+    fn call(&self, request: &[u8]) -> Result<Vec<u8>, Error> {
+        decode_response(self.transport.call(request)?)
+    }
+}
+
+impl<'a, T: Transport> KeyValueStore<'a> for Client<T> {
+    type Batch = RemoteBatch<T>;
+    type Snapshot = RemoteSnapshot;
+
+    fn new(path: &str) -> Self {
+        Self::open(path, &serde_json::Value::Null).expect("Failed to open the client")
+    }
+
+    fn open(path: &str, options: &serde_json::Value) -> Result<Self, Error> {
+        Ok(Self::from_transport(T::open(path, options)?))
+    }
+
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        let mut request = vec![REQUEST_GET];
+        write_bytes(&mut request, key)?;
+        Ok(read_option(&mut &self.call(&request)?[..])?)
+    }
+
+    fn multi_get(&self, keys: &[&[u8]]) -> Result<Vec<Option<Vec<u8>>>, Error> {
+        let mut request = counted(REQUEST_MULTI_GET, keys.len());
+        for key in keys {
+            write_bytes(&mut request, key)?;
+        }
+        let response = self.call(&request)?;
+        let mut body = &response[..];
+        let len = read_u32(&mut body)?;
+        Ok((0..len)
+            .map(|_| read_option(&mut body))
+            .collect::<Result<_, _>>()?)
+    }
+
+    fn exists(&self, key: &[u8]) -> Result<bool, Error> {
+        let mut request = vec![REQUEST_EXISTS];
+        write_bytes(&mut request, key)?;
+        Ok(read_u8(&mut &self.call(&request)?[..])? != 0)
+    }
+
+    fn scan(&self, start: &[u8], count: usize) -> Result<Vec<KeyValue>, Error> {
+        let mut request = vec![REQUEST_SCAN];
+        write_bytes(&mut request, start)?;
+        request.extend_from_slice(&(count as u64).to_le_bytes());
+        let response = self.call(&request)?;
+        let mut body = &response[..];
+        let len = read_u32(&mut body)?;
+        Ok((0..len)
+            .map(|_| Ok((read_bytes(&mut body)?, read_bytes(&mut body)?)))
+            .collect::<io::Result<_>>()?)
+    }
+
+    fn batch(&self) -> Result<Self::Batch, Error> {
+        Ok(RemoteBatch {
+            transport: Arc::clone(&self.transport),
+            request: counted(REQUEST_BATCH, 0),
+            len: 0,
+        })
+    }
+
+    // the pairs are sent by large requests, the server bulk loads each of them
+    fn bulk_load<I: Iterator<Item = KeyValue>>(&self, pairs: I) -> Result<(), Error> {
+        let mut pairs = pairs.peekable();
+        while pairs.peek().is_some() {
+            let mut request = counted(REQUEST_BULK_LOAD, 0);
+            let mut len = 0;
+            for (key, value) in pairs.by_ref() {
+                write_bytes(&mut request, &key)?;
+                write_bytes(&mut request, &value)?;
+                len += 1;
+                if request.len() >= BULK_LOAD_REQUEST_SIZE {
+                    break;
+                }
+            }
+            set_count(&mut request, len);
+            self.call(&request)?;
+        }
+        Ok(())
+    }
+
+    // a snapshot would have to live in the server between the requests
+    fn snapshot(&'a self) -> Result<Self::Snapshot, Error> {
+        Err(Error::DBError("a client has no snapshots".to_string()))
+    }
+
+    fn compare_and_swap(
+        &self,
+        key: &[u8],
+        old: Option<&[u8]>,
+        new: Option<&[u8]>,
+    ) -> Result<bool, Error> {
+        let mut request = vec![REQUEST_COMPARE_AND_SWAP];
+        write_bytes(&mut request, key)?;
+        write_option(&mut request, old)?;
+        write_option(&mut request, new)?;
+        Ok(read_u8(&mut &self.call(&request)?[..])? != 0)
+    }
+
+    fn add(&self, key: &[u8], delta: u64) -> Result<(), Error> {
+        let mut request = vec![REQUEST_ADD];
+        write_bytes(&mut request, key)?;
+        request.extend_from_slice(&delta.to_le_bytes());
+        self.call(&request)?;
+        Ok(())
+    }
+}
+
+/// The connection of `remote::Store` to its server.
+pub struct Connection {
+    stream: Mutex<(BufReader<Stream>, BufWriter<Stream>)>,
+}
+
+impl Transport for Connection {
+    fn open(path: &str, options: &serde_json::Value) -> Result<Self, Error> {
+        if !options.is_null() {
+            return Err(Error::WorkloadError(format!(
+                "the store has no options: {}",
+                options
+            )));
+        }
+        let stream = Stream::connect(path)?;
+        let connection = Self {
+            stream: Mutex::new((BufReader::new(stream.try_clone()?), BufWriter::new(stream))),
+        };
+        decode_response(connection.call(&PROTOCOL_VERSION.to_le_bytes())?)?;
+        Ok(connection)
+    }
+
+    fn call(&self, request: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut stream = self.stream.lock().unwrap();
+        let (reader, writer) = &mut *stream;
+        write_bytes(writer, request)?;
+        writer.flush()?;
+        Ok(read_bytes(reader)?)
+    }
+}
+
+fn decode_response(mut response: Vec<u8>) -> Result<Vec<u8>, Error> {
+    let status = *response
+        .first()
+        .ok_or_else(|| Error::DBError("the response is empty".to_string()))?;
+    if status == STATUS_OK {
+        response.drain(..1);
+        return Ok(response);
+    }
+    let message = String::from_utf8_lossy(&read_bytes(&mut &response[1..])?).into_owned();
+    Err(match status {
+        STATUS_IO_ERROR => Error::IOError(message),
+        STATUS_WORKLOAD_ERROR => Error::WorkloadError(message),
+        STATUS_CONFLICT_ERROR => Error::ConflictError(message),
+        _ => Error::DBError(message),
+    })
+}
+
+// the operations are encoded as they're added, and sent by one request when committing
+pub struct RemoteBatch<T> {
+    transport: Arc<T>,
+    request: Vec<u8>,
+    len: usize,
+}
+
+impl<T: Transport> Batch for RemoteBatch<T> {
+    fn put(&mut self, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        self.request.push(OPERATION_PUT);
+        write_bytes(&mut self.request, key)?;
+        write_bytes(&mut self.request, value)?;
+        self.len += 1;
+        Ok(())
+    }
+
+    fn delete(&mut self, key: &[u8]) -> Result<(), Error> {
+        self.request.push(OPERATION_DELETE);
+        write_bytes(&mut self.request, key)?;
+        self.len += 1;
+        Ok(())
+    }
+
+    fn delete_range(&mut self, start: &[u8], end: &[u8]) -> Result<(), Error> {
+        self.request.push(OPERATION_DELETE_RANGE);
+        write_bytes(&mut self.request, start)?;
+        write_bytes(&mut self.request, end)?;
+        self.len += 1;
+        Ok(())
+    }
+
+    fn commit(mut self) -> Result<(), Error> {
+        set_count(&mut self.request, self.len);
+        decode_response(self.transport.call(&self.request)?)?;
+        Ok(())
+    }
+}
+
+/// Never built, `Client::snapshot` always fails.
+pub enum RemoteSnapshot {}
+
+impl Snapshot for RemoteSnapshot {
+    fn get(&self, _key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        match *self {}
+    }
+
+    fn multi_get(&self, _keys: &[&[u8]]) -> Result<Vec<Option<Vec<u8>>>, Error> {
+        match *self {}
+    }
+
+    fn exists(&self, _key: &[u8]) -> Result<bool, Error> {
+        match *self {}
+    }
+}
+
+/// Where `serve` accepts the connections of `remote::Store`, bound to a TCP address such
+/// as `127.0.0.1:7878`, or else to the path of a Unix socket on Unix.
+pub enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener),
+}
+
+impl Listener {
+    pub fn bind(address: &str) -> Result<Self, Error> {
+        match address.parse::<SocketAddr>() {
+            Ok(address) => Ok(Listener::Tcp(TcpListener::bind(address)?)),
+            #[cfg(not(unix))]
+            Err(_) => Err(Error::WorkloadError(format!(
+                "{} is not a TCP address",
+                address
+            ))),
+            #[cfg(unix)]
+            Err(_) => {
+                // the socket of a previous server is replaced
+                if fs::metadata(address)
+                    .map(|metadata| metadata.file_type().is_socket())
+                    .unwrap_or(false)
+                {
+                    fs::remove_file(address)?;
+                }
+                Ok(Listener::Unix(UnixListener::bind(address)?))
+            }
+        }
+    }
+
+    // the address to connect to, with the port picked by the system when binding to port 0
+    pub fn address(&self) -> Result<String, Error> {
+        match self {
+            Listener::Tcp(listener) => Ok(listener.local_addr()?.to_string()),
+            #[cfg(unix)]
+            Listener::Unix(listener) => Ok(listener
+                .local_addr()?
+                .as_pathname()
+                .map(|path| path.display().to_string())
+                .unwrap_or_default()),
+        }
+    }
+
+    fn accept(&self) -> io::Result<Stream> {
+        match self {
+            Listener::Tcp(listener) => {
+                let (stream, _) = listener.accept()?;
+                stream.set_nodelay(true)?;
+                Ok(Stream::Tcp(stream))
+            }
+            #[cfg(unix)]
+            Listener::Unix(listener) => Ok(Stream::Unix(listener.accept()?.0)),
+        }
+    }
+}
+
+/// Serves the requests of `remote::Store` with `store` forever, each connection in its
+/// own thread.
+pub fn serve(store: &dyn DynStore, listener: Listener) -> Result<(), Error> {
+    thread::scope(|scope| loop {
+        match listener.accept() {
+            // a failed connection is closed, the server goes on with the others
+            Ok(stream) => {
+                scope.spawn(move || serve_connection(store, stream));
+            }
+            // e.g. an aborted connection, or no file descriptor left for a moment
+            Err(e) => {
+                eprintln!("failed to accept a connection: {}", e);
+                thread::sleep(Duration::from_millis(10));
+            }
+        }
+    })
+}
+
+fn serve_connection(store: &dyn DynStore, stream: Stream) -> Result<(), Error> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+    // the first frame is the protocol version of the client
+    if read_bytes(&mut reader)? != PROTOCOL_VERSION.to_le_bytes() {
+        let e = Error::WorkloadError(format!(
+            "the server only has the protocol version {}",
+            PROTOCOL_VERSION
+        ));
+        write_bytes(&mut writer, &respond_error(e))?;
+        writer.flush()?;
+        return Ok(());
+    }
+    write_bytes(&mut writer, &[STATUS_OK])?;
+    writer.flush()?;
+    // until the client closes the connection
+    while !reader.fill_buf()?.is_empty() {
+        let request = read_bytes(&mut reader)?;
+        write_bytes(&mut writer, &respond(store, &request))?;
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+// the response to the frame of a request, with the error of the store when it fails
+pub(crate) fn respond(store: &dyn DynStore, request: &[u8]) -> Vec<u8> {
+    handle(store, request).unwrap_or_else(respond_error)
+}
+
+fn handle(store: &dyn DynStore, mut request: &[u8]) -> Result<Vec<u8>, Error> {
+    let r = &mut request;
+    let mut response = vec![STATUS_OK];
+    match read_u8(r)? {
+        REQUEST_GET => write_option(&mut response, store.get(&read_bytes(r)?)?.as_deref())?,
+        REQUEST_EXISTS => response.push(store.exists(&read_bytes(r)?)? as u8),
+        REQUEST_MULTI_GET => {
+            let len = read_u32(r)?;
+            let keys = (0..len)
+                .map(|_| read_bytes(r))
+                .collect::<io::Result<Vec<_>>>()?;
+            let keys = keys.iter().map(|key| &key[..]).collect::<Vec<_>>();
+            let values = store.multi_get(&keys)?;
+            response.extend_from_slice(&(values.len() as u32).to_le_bytes());
+            for value in values {
+                write_option(&mut response, value.as_deref())?;
+            }
+        }
+        REQUEST_SCAN => {
+            let start = read_bytes(r)?;
+            let pairs = store.scan(&start, read_u64(r)? as usize)?;
+            response.extend_from_slice(&(pairs.len() as u32).to_le_bytes());
+            for (key, value) in pairs {
+                write_bytes(&mut response, &key)?;
+                write_bytes(&mut response, &value)?;
+            }
+        }
+        REQUEST_BATCH => {
+            let mut batch = store.batch()?;
+            for _ in 0..read_u32(r)? {
+                match read_u8(r)? {
+                    OPERATION_PUT => batch.put(&read_bytes(r)?, &read_bytes(r)?)?,
+                    OPERATION_DELETE => batch.delete(&read_bytes(r)?)?,
+                    OPERATION_DELETE_RANGE => {
+                        batch.delete_range(&read_bytes(r)?, &read_bytes(r)?)?
+                    }
+                    tag => {
+                        return Err(Error::WorkloadError(format!(
+                            "unknown batch operation tag: {}",
+                            tag
+                        )))
+                    }
+                }
+            }
+            batch.commit()?;
+        }
+        REQUEST_BULK_LOAD => {
+            let len = read_u32(r)?;
+            let pairs = (0..len)
+                .map(|_| Ok((read_bytes(r)?, read_bytes(r)?)))
+                .collect::<io::Result<Vec<_>>>()?;
+            store.bulk_load(&mut pairs.into_iter())?;
+        }
+        REQUEST_COMPARE_AND_SWAP => {
+            let key = read_bytes(r)?;
+            let old = read_option(r)?;
+            let new = read_option(r)?;
+            let swapped = store.compare_and_swap(&key, old.as_deref(), new.as_deref())?;
+            response.push(swapped as u8);
+        }
+        REQUEST_ADD => {
+            let key = read_bytes(r)?;
+            store.add(&key, read_u64(r)?)?;
+        }
+        tag => {
+            return Err(Error::WorkloadError(format!(
+                "unknown request tag: {}",
+                tag
+            )))
+        }
+    }
+    Ok(response)
+}
+
+// the response of a failed request
+pub(crate) fn respond_error(e: Error) -> Vec<u8> {
+    let (status, message) = match e {
+        Error::DBError(message) => (STATUS_DB_ERROR, message),
+        Error::IOError(message) => (STATUS_IO_ERROR, message),
+        Error::WorkloadError(message) => (STATUS_WORKLOAD_ERROR, message),
+        Error::ConflictError(message) => (STATUS_CONFLICT_ERROR, message),
+    };
+    let mut response = vec![status];
+    response.extend_from_slice(&(message.len() as u32).to_le_bytes());
+    response.extend_from_slice(message.as_bytes());
+    response
+}
+
+// a request with its tag and the number of items which follow it
+fn counted(tag: u8, len: usize) -> Vec<u8> {
+    let mut request = vec![tag];
+    request.extend_from_slice(&(len as u32).to_le_bytes());
+    request
+}
+
+fn set_count(request: &mut [u8], len: usize) {
+    request[1..5].copy_from_slice(&(len as u32).to_le_bytes());
+}
+
+fn write_option<W: Write>(w: &mut W, bytes: Option<&[u8]>) -> io::Result<()> {
+    match bytes {
+        Some(bytes) => {
+            w.write_all(&[1])?;
+            write_bytes(w, bytes)
+        }
+        None => w.write_all(&[0]),
+    }
+}
+
+fn read_option<R: BufRead>(reader: &mut R) -> io::Result<Option<Vec<u8>>> {
+    match read_u8(reader)? {
+        0 => Ok(None),
+        _ => read_bytes(reader).map(Some),
+    }
+}
+
+enum Stream {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl Stream {
+    fn connect(address: &str) -> io::Result<Self> {
+        match address.parse::<SocketAddr>() {
+            Ok(address) => {
+                let stream = TcpStream::connect(address)?;
+                // the requests are flushed whole, they're sent without waiting for an ack
+                stream.set_nodelay(true)?;
+                Ok(Stream::Tcp(stream))
+            }
+            #[cfg(unix)]
+            Err(_) => Ok(Stream::Unix(UnixStream::connect(address)?)),
+            #[cfg(not(unix))]
+            Err(_) => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is not a TCP address", address),
+            )),
+        }
+    }
+
+    fn try_clone(&self) -> io::Result<Self> {
+        match self {
+            Stream::Tcp(stream) => stream.try_clone().map(Stream::Tcp),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.try_clone().map(Stream::Unix),
+        }
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(stream) => stream.read(buf),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(stream) => stream.write(buf),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Tcp(stream) => stream.flush(),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_response, serve, Listener, Store};
+    use crate::conformance::Capabilities;
+    use crate::format::{read_bytes, write_bytes};
+    use crate::memory::Store as Memory;
+    use crate::{Batch, Error, KeyValueStore};
+    use std::io::BufReader;
+    use std::net::TcpStream;
+    use std::thread;
+    #[cfg(unix)]
+    use tempfile;
+
+    // serves an in-memory store in the background, returns the address to connect to
+    fn serve_memory(address: &str) -> String {
+        let listener = Listener::bind(address).unwrap();
+        let address = listener.address().unwrap();
+        thread::spawn(move || serve(&Memory::new(""), listener));
+        address
+    }
+
+    // a new server for each check, a reopen connects again to it, which keeps the pairs
+    fn open_served(_: &str) -> impl Fn() -> Store {
+        let address = serve_memory("127.0.0.1:0");
+        move || Store::new(&address)
+    }
+
+    fn capabilities() -> Capabilities {
+        Capabilities {
+            snapshots: false,
+            ..Capabilities::default()
+        }
+    }
+
+    crate::conformance_tests!(Store, open_served, capabilities());
+    crate::conformance::model_tests!(Store, open_served, capabilities());
+
+    fn put_and_get(store: &Store) {
+        let mut batch = store.batch().unwrap();
+        batch.put(&[0, 0], &[0, 0, 0]).unwrap();
+        batch.put(&[1, 1], &[1, 1, 1]).unwrap();
+        batch.put(&[], &[]).unwrap();
+        batch.commit().unwrap();
+
+        assert_eq!(Some(vec![0, 0, 0]), store.get(&[0, 0]).unwrap());
+        assert_eq!(Some(vec![]), store.get(&[]).unwrap());
+        assert_eq!(None, store.get(&[2, 2]).unwrap());
+        assert!(store.exists(&[1, 1]).unwrap());
+        assert!(!store.exists(&[2, 2]).unwrap());
+        assert_eq!(
+            vec![Some(vec![1, 1, 1]), None, Some(vec![0, 0, 0])],
+            store.multi_get(&[&[1, 1], &[2, 2], &[0, 0]]).unwrap()
+        );
+    }
+
+    #[test]
+    fn tcp() {
+        let store = Store::new(&serve_memory("127.0.0.1:0"));
+        put_and_get(&store);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unix_socket() {
+        let tmp_dir = tempfile::Builder::new()
+            .prefix("unix_socket")
+            .tempdir()
+            .unwrap();
+        let path = tmp_dir.path().join("socket");
+        let store = Store::new(&serve_memory(path.to_str().unwrap()));
+        put_and_get(&store);
+    }
+
+    #[test]
+    fn errors() {
+        let store = Store::new(&serve_memory("127.0.0.1:0"));
+        match store.call(&[42]) {
+            Err(Error::WorkloadError(message)) => assert_eq!(message, "unknown request tag: 42"),
+            _ => panic!("the request is served"),
+        }
+        // the connection is still usable after an error
+        assert!(!store.exists(&[0]).unwrap());
+        assert!(decode_response(Vec::new()).is_err());
+    }
+
+    #[test]
+    fn other_protocol_version() {
+        let mut stream = TcpStream::connect(serve_memory("127.0.0.1:0")).unwrap();
+        write_bytes(&mut stream, &1u32.to_le_bytes()).unwrap();
+        match decode_response(read_bytes(&mut BufReader::new(stream)).unwrap()) {
+            Err(Error::WorkloadError(message)) => {
+                assert_eq!(message, "the server only has the protocol version 2")
+            }
+            _ => panic!("the client is served"),
+        }
+    }
+}
@@ -1,28 +1,111 @@
-use crate::{Batch, Error, KeyValueStore};
-use rocksdb::{self, WriteBatch, DB};
+use crate::buffered::Writes;
+use crate::preset::{Compression, LsmPreset, MEMTABLES};
+use crate::{
+    counter, parse_options, Batch, Error, KeyValue, KeyValueStore, Snapshot, Transaction,
+    TransactionalStore,
+};
+use rocksdb::{
+    self, BlockBasedOptions, DBAccess, DBCompressionType, DBIteratorWithThreadMode, Direction,
+    ErrorKind, IngestExternalFileOptions, IteratorMode, MergeOperands, OptimisticTransactionDB,
+    Options, ReadOptions, SnapshotWithThreadMode, SstFileWriter, TransactionDB,
+    TransactionDBOptions, WriteBatch, WriteBatchIterator, WriteBatchWithTransaction, DB,
+};
+use std::path::PathBuf;
 use std::sync::Arc;
 
+// the bulk load starts a new SST file when one reaches this size
+const SST_FILE_SIZE: u64 = 256 << 20;
+// bytes of keys and values in each batch of a bulk load without SST files
+const BULK_LOAD_BATCH_SIZE: usize = 64 << 20;
+
 pub struct Store {
     db: Arc<DB>,
+    // the SST files of a bulk load are built in the database directory
+    path: PathBuf,
+    // the SST files of a bulk load take the compression and the blocks of the store
+    opts: Options,
+}
+
+// the default options with the `add` merge operator
+fn options() -> Options {
+    let mut opts = Options::default();
+    opts.create_if_missing(true);
+    opts.set_merge_operator_associative("add", add_operands);
+    opts
+}
+
+// RocksDB can apply every setting of the preset
+fn apply_preset(opts: &mut Options, preset: &LsmPreset) {
+    opts.set_write_buffer_size(preset.memtable_size as usize);
+    opts.set_max_write_buffer_number(MEMTABLES as i32);
+    opts.set_compression_type(match preset.compression {
+        Compression::None => DBCompressionType::None,
+        Compression::Lz4 => DBCompressionType::Lz4,
+        Compression::Zlib => DBCompressionType::Zlib,
+        Compression::Snappy => DBCompressionType::Snappy,
+        Compression::Zstd => DBCompressionType::Zstd,
+    });
+    let mut table = BlockBasedOptions::default();
+    table.set_block_size(preset.block_size as usize);
+    if let Some(bits) = preset.bloom_bits_per_key {
+        table.set_bloom_filter(f64::from(bits), false);
+    }
+    opts.set_block_based_table_factory(&table);
+}
+
+fn add_operands(_key: &[u8], existing: Option<&[u8]>, operands: &MergeOperands) -> Option<Vec<u8>> {
+    let sum = operands.into_iter().fold(counter(existing), |sum, delta| {
+        sum.wrapping_add(counter(Some(delta)))
+    });
+    Some(sum.to_le_bytes().to_vec())
 }
 
 impl<'a> KeyValueStore<'a> for Store {
     type Batch = RocksdbBatch;
+    type Snapshot = RocksdbSnapshot<'a>;
 
     fn new(path: &str) -> Self {
-        let db = Arc::new(DB::open_default(path).expect("Failed to open rocksdb"));
-        Self { db }
+        Self::open(path, &serde_json::Value::Null).expect("Failed to open rocksdb")
+    }
+
+    // the default options of RocksDB without options, the LSM preset otherwise
+    fn open(path: &str, options: &serde_json::Value) -> Result<Self, Error> {
+        let mut opts = self::options();
+        if !options.is_null() {
+            apply_preset(&mut opts, &parse_options(options)?);
+        }
+        Ok(Self {
+            db: Arc::new(DB::open(&opts, path)?),
+            path: PathBuf::from(path),
+            opts,
+        })
     }
 
     fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
         self.db
-            .get(&key)
+            .get(key)
             .map(|v| v.map(|vi| vi.to_vec()))
             .map_err(Into::into)
     }
 
+    fn multi_get(&self, keys: &[&[u8]]) -> Result<Vec<Option<Vec<u8>>>, Error> {
+        self.db
+            .multi_get(keys)
+            .into_iter()
+            .map(|v| v.map_err(Into::into))
+            .collect()
+    }
+
     fn exists(&self, key: &[u8]) -> Result<bool, Error> {
-        self.db.get(&key).map(|v| v.is_some()).map_err(Into::into)
+        self.db.get(key).map(|v| v.is_some()).map_err(Into::into)
+    }
+
+    fn scan(&self, start: &[u8], count: usize) -> Result<Vec<KeyValue>, Error> {
+        scan(
+            self.db
+                .iterator(IteratorMode::From(start, Direction::Forward)),
+            count,
+        )
     }
 
     fn batch(&self) -> Result<Self::Batch, Error> {
@@ -31,6 +114,73 @@ impl<'a> KeyValueStore<'a> for Store {
             wb: WriteBatch::default(),
         })
     }
+
+    // the SST files are moved into the database, the pairs skip the memtable and the compactions
+    fn bulk_load<I: Iterator<Item = KeyValue>>(&self, pairs: I) -> Result<(), Error> {
+        let mut files = Vec::new();
+        let mut writer: Option<SstFileWriter> = None;
+        for (key, value) in pairs {
+            if writer
+                .as_ref()
+                .is_none_or(|w| w.file_size() >= SST_FILE_SIZE)
+            {
+                if let Some(mut full) = writer.take() {
+                    full.finish()?;
+                }
+                let file = self.path.join(format!("bulk_load_{}.sst", files.len()));
+                let new = SstFileWriter::create(&self.opts);
+                new.open(&file)?;
+                files.push(file);
+                writer = Some(new);
+            }
+            writer.as_mut().unwrap().put(key, value)?;
+        }
+        match writer {
+            Some(mut last) => last.finish()?,
+            None => return Ok(()),
+        }
+        let mut ingest = IngestExternalFileOptions::default();
+        ingest.set_move_files(true);
+        self.db.ingest_external_file_opts(&ingest, files)?;
+        Ok(())
+    }
+
+    fn snapshot(&'a self) -> Result<Self::Snapshot, Error> {
+        Ok(RocksdbSnapshot {
+            snapshot: self.db.snapshot(),
+        })
+    }
+
+    // DB has no transaction to check the value and write it atomically
+    fn compare_and_swap(
+        &self,
+        _key: &[u8],
+        _old: Option<&[u8]>,
+        _new: Option<&[u8]>,
+    ) -> Result<bool, Error> {
+        Err(Error::DBError(
+            "rocksdb has no compare and swap, use rocksdb_optimistic or rocksdb_pessimistic"
+                .to_string(),
+        ))
+    }
+
+    fn add(&self, key: &[u8], delta: u64) -> Result<(), Error> {
+        self.db.merge(key, delta.to_le_bytes())?;
+        Ok(())
+    }
+}
+
+// the deleted ranges are skipped over by the iterators until a compaction drops them
+fn scan<D: DBAccess>(
+    iter: DBIteratorWithThreadMode<D>,
+    count: usize,
+) -> Result<Vec<KeyValue>, Error> {
+    iter.take(count)
+        .map(|item| {
+            item.map(|(key, value)| (key.to_vec(), value.to_vec()))
+                .map_err(Into::into)
+        })
+        .collect()
 }
 
 pub struct RocksdbBatch {
@@ -40,12 +190,18 @@ pub struct RocksdbBatch {
 
 impl Batch for RocksdbBatch {
     fn put(&mut self, key: &[u8], value: &[u8]) -> Result<(), Error> {
-        self.wb.put(key, value)?;
+        self.wb.put(key, value);
         Ok(())
     }
 
     fn delete(&mut self, key: &[u8]) -> Result<(), Error> {
-        self.wb.delete(key)?;
+        self.wb.delete(key);
+        Ok(())
+    }
+
+    // one range tombstone in the default column family
+    fn delete_range(&mut self, start: &[u8], end: &[u8]) -> Result<(), Error> {
+        self.wb.delete_range(start, end);
         Ok(())
     }
 
@@ -55,59 +211,419 @@ impl Batch for RocksdbBatch {
     }
 }
 
+pub struct RocksdbSnapshot<'a, D: DBAccess = DB> {
+    snapshot: SnapshotWithThreadMode<'a, D>,
+}
+
+impl<'a, D: DBAccess> Snapshot for RocksdbSnapshot<'a, D> {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        self.snapshot.get(key).map_err(Into::into)
+    }
+
+    fn multi_get(&self, keys: &[&[u8]]) -> Result<Vec<Option<Vec<u8>>>, Error> {
+        self.snapshot
+            .multi_get(keys)
+            .into_iter()
+            .map(|v| v.map_err(Into::into))
+            .collect()
+    }
+
+    fn exists(&self, key: &[u8]) -> Result<bool, Error> {
+        self.snapshot
+            .get(key)
+            .map(|v| v.is_some())
+            .map_err(Into::into)
+    }
+}
+
+/// RocksDB opened as an `OptimisticTransactionDB` or a `TransactionDB`, the batches also go
+/// through the transaction layer, which makes them slower than the ones of `Store`.
+pub struct TransactionStore<D> {
+    db: Arc<D>,
+}
+
+// conflicts are detected when the transaction commits
+pub type OptimisticStore = TransactionStore<OptimisticTransactionDB>;
+// the keys read by a transaction are locked until it commits
+pub type PessimisticStore = TransactionStore<TransactionDB>;
+
+/// The operations shared by the two transaction databases of RocksDB.
+pub trait TransactionDb: DBAccess + Sized {
+    fn open(opts: &Options, path: &str) -> Result<Self, rocksdb::Error>;
+    fn write(&self, batch: WriteBatchWithTransaction<true>) -> Result<(), rocksdb::Error>;
+    fn transaction(&self) -> rocksdb::Transaction<'_, Self>;
+    fn iterator(&self, mode: IteratorMode) -> DBIteratorWithThreadMode<'_, Self>;
+}
+
+impl TransactionDb for OptimisticTransactionDB {
+    fn open(opts: &Options, path: &str) -> Result<Self, rocksdb::Error> {
+        OptimisticTransactionDB::open(opts, path)
+    }
+
+    fn write(&self, batch: WriteBatchWithTransaction<true>) -> Result<(), rocksdb::Error> {
+        OptimisticTransactionDB::write(self, batch)
+    }
+
+    fn transaction(&self) -> rocksdb::Transaction<'_, Self> {
+        OptimisticTransactionDB::transaction(self)
+    }
+
+    fn iterator(&self, mode: IteratorMode) -> DBIteratorWithThreadMode<'_, Self> {
+        OptimisticTransactionDB::iterator(self, mode)
+    }
+}
+
+impl TransactionDb for TransactionDB {
+    fn open(opts: &Options, path: &str) -> Result<Self, rocksdb::Error> {
+        TransactionDB::open(opts, &TransactionDBOptions::default(), path)
+    }
+
+    fn write(&self, batch: WriteBatchWithTransaction<true>) -> Result<(), rocksdb::Error> {
+        TransactionDB::write(self, batch)
+    }
+
+    fn transaction(&self) -> rocksdb::Transaction<'_, Self> {
+        TransactionDB::transaction(self)
+    }
+
+    fn iterator(&self, mode: IteratorMode) -> DBIteratorWithThreadMode<'_, Self> {
+        TransactionDB::iterator(self, mode)
+    }
+}
+
+impl<'a, D: TransactionDb + 'a> KeyValueStore<'a> for TransactionStore<D> {
+    type Batch = RocksdbTransactionBatch<D>;
+    type Snapshot = RocksdbSnapshot<'a, D>;
+
+    fn new(path: &str) -> Self {
+        Self::open(path, &serde_json::Value::Null).expect("Failed to open rocksdb")
+    }
+
+    // the options of `Store`
+    fn open(path: &str, options: &serde_json::Value) -> Result<Self, Error> {
+        let mut opts = self::options();
+        if !options.is_null() {
+            apply_preset(&mut opts, &parse_options(options)?);
+        }
+        Ok(Self {
+            db: Arc::new(D::open(&opts, path)?),
+        })
+    }
+
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        self.db
+            .get_opt(key, &ReadOptions::default())
+            .map_err(Into::into)
+    }
+
+    fn multi_get(&self, keys: &[&[u8]]) -> Result<Vec<Option<Vec<u8>>>, Error> {
+        self.db
+            .multi_get_opt(keys, &ReadOptions::default())
+            .into_iter()
+            .map(|v| v.map_err(Into::into))
+            .collect()
+    }
+
+    fn exists(&self, key: &[u8]) -> Result<bool, Error> {
+        self.get(key).map(|v| v.is_some())
+    }
+
+    fn scan(&self, start: &[u8], count: usize) -> Result<Vec<KeyValue>, Error> {
+        scan(
+            self.db
+                .iterator(IteratorMode::From(start, Direction::Forward)),
+            count,
+        )
+    }
+
+    fn batch(&self) -> Result<Self::Batch, Error> {
+        Ok(Self::Batch {
+            db: Arc::clone(&self.db),
+            wb: WriteBatchWithTransaction::default(),
+            ranged: None,
+        })
+    }
+
+    // the pairs go through the transaction layer in large batches
+    fn bulk_load<I: Iterator<Item = KeyValue>>(&self, pairs: I) -> Result<(), Error> {
+        let mut wb = WriteBatchWithTransaction::default();
+        for (key, value) in pairs {
+            wb.put(key, value);
+            if wb.size_in_bytes() >= BULK_LOAD_BATCH_SIZE {
+                self.db.write(std::mem::take(&mut wb))?;
+            }
+        }
+        if !wb.is_empty() {
+            self.db.write(wb)?;
+        }
+        Ok(())
+    }
+
+    fn snapshot(&'a self) -> Result<Self::Snapshot, Error> {
+        Ok(RocksdbSnapshot {
+            snapshot: SnapshotWithThreadMode::new(&*self.db),
+        })
+    }
+
+    // retries the transactions which conflict with a concurrent write of the key
+    fn compare_and_swap(
+        &self,
+        key: &[u8],
+        old: Option<&[u8]>,
+        new: Option<&[u8]>,
+    ) -> Result<bool, Error> {
+        loop {
+            let txn = self.db.transaction();
+            if txn.get_for_update(key, true)?.as_deref() != old {
+                return Ok(false);
+            }
+            match new {
+                Some(value) => txn.put(key, value)?,
+                None => txn.delete(key)?,
+            }
+            match txn.commit().map_err(Error::from) {
+                Ok(()) => return Ok(true),
+                Err(Error::ConflictError(_)) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn add(&self, key: &[u8], delta: u64) -> Result<(), Error> {
+        let mut wb = WriteBatchWithTransaction::default();
+        wb.merge(key, delta.to_le_bytes());
+        self.db.write(wb)?;
+        Ok(())
+    }
+}
+
+impl<'a, D: TransactionDb + 'a> TransactionalStore<'a> for TransactionStore<D> {
+    type Transaction = RocksdbTransaction<'a, D>;
+
+    fn transaction(&'a self) -> Result<Self::Transaction, Error> {
+        Ok(RocksdbTransaction {
+            txn: self.db.transaction(),
+        })
+    }
+}
+
+pub struct RocksdbTransactionBatch<D> {
+    db: Arc<D>,
+    wb: WriteBatchWithTransaction<true>,
+    // the writes from the first range delete
+    ranged: Option<Writes>,
+}
+
+impl<D: TransactionDb> Batch for RocksdbTransactionBatch<D> {
+    fn put(&mut self, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        match &mut self.ranged {
+            Some(writes) => writes.put(key, value),
+            None => self.wb.put(key, value),
+        }
+        Ok(())
+    }
+
+    fn delete(&mut self, key: &[u8]) -> Result<(), Error> {
+        match &mut self.ranged {
+            Some(writes) => writes.delete(key),
+            None => self.wb.delete(key),
+        }
+        Ok(())
+    }
+
+    fn delete_range(&mut self, start: &[u8], end: &[u8]) -> Result<(), Error> {
+        let mut puts = RangePuts {
+            start,
+            end,
+            keys: Vec::new(),
+        };
+        self.wb.iterate(&mut puts);
+        for key in puts.keys {
+            self.wb.delete(key);
+        }
+        self.ranged
+            .get_or_insert_with(Writes::default)
+            .delete_range(start, end);
+        Ok(())
+    }
+
+    // the transaction databases don't take range tombstones, the keys of a range are
+    // deleted one by one
+    fn commit(mut self) -> Result<(), Error> {
+        if let Some(writes) = self.ranged.take() {
+            let db = &self.db;
+            let changes = writes.changes(|start, end| {
+                let mut keys = Vec::new();
+                for item in db.iterator(IteratorMode::From(start, Direction::Forward)) {
+                    let (key, _) = item?;
+                    if &key[..] >= end {
+                        break;
+                    }
+                    keys.push(key.to_vec());
+                }
+                Ok(keys)
+            })?;
+            for (key, value) in changes {
+                match value {
+                    Some(value) => self.wb.put(key, value),
+                    None => self.wb.delete(key),
+                }
+            }
+        }
+        self.db.write(self.wb)?;
+        Ok(())
+    }
+}
+
+// the keys put in a write batch from `start` included to `end` excluded
+struct RangePuts<'r> {
+    start: &'r [u8],
+    end: &'r [u8],
+    keys: Vec<Vec<u8>>,
+}
+
+impl<'r> WriteBatchIterator for RangePuts<'r> {
+    fn put(&mut self, key: &[u8], _value: &[u8]) {
+        if self.start <= key && key < self.end {
+            self.keys.push(key.to_vec());
+        }
+    }
+
+    fn delete(&mut self, _key: &[u8]) {}
+}
+
+pub struct RocksdbTransaction<'a, D> {
+    txn: rocksdb::Transaction<'a, D>,
+}
+
+impl<'a, D> Transaction for RocksdbTransaction<'a, D> {
+    // the read key takes part in the conflict detection, as with `SELECT ... FOR UPDATE`
+    fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        self.txn.get_for_update(key, true).map_err(Into::into)
+    }
+
+    fn put(&mut self, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        self.txn.put(key, value)?;
+        Ok(())
+    }
+
+    fn delete(&mut self, key: &[u8]) -> Result<(), Error> {
+        self.txn.delete(key)?;
+        Ok(())
+    }
+
+    fn commit(self) -> Result<(), Error> {
+        self.txn.commit()?;
+        Ok(())
+    }
+}
+
 impl From<rocksdb::Error> for Error {
     fn from(e: rocksdb::Error) -> Error {
-        Error::DBError(e.to_string())
+        match e.kind() {
+            // failed validation of an optimistic transaction, lock timeout or deadlock
+            ErrorKind::Busy | ErrorKind::TryAgain | ErrorKind::TimedOut => {
+                Error::ConflictError(e.to_string())
+            }
+            _ => Error::DBError(e.to_string()),
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::conformance::Capabilities;
     use tempfile;
 
+    fn capabilities() -> Capabilities {
+        Capabilities {
+            compare_and_swap: false,
+            ..Capabilities::default()
+        }
+    }
+
+    crate::conformance_tests!(Store, capabilities());
+    crate::conformance::model_tests!(Store, capabilities());
+
+    mod optimistic {
+        crate::conformance_tests!(crate::rocksdb::OptimisticStore);
+        crate::conformance::model_tests!(crate::rocksdb::OptimisticStore);
+    }
+
+    mod pessimistic {
+        crate::conformance_tests!(crate::rocksdb::PessimisticStore);
+        crate::conformance::model_tests!(crate::rocksdb::PessimisticStore);
+    }
+
     #[test]
-    fn put_and_get() {
+    fn bulk_load() {
         let tmp_dir = tempfile::Builder::new()
-            .prefix("put_and_get")
+            .prefix("bulk_load")
             .tempdir()
             .unwrap();
         let store = Store::new(tmp_dir.path().to_str().unwrap());
-        let mut batch = store.batch().unwrap();
-        batch.put(&[0, 0], &[0, 0, 0]).unwrap();
-        batch.put(&[1, 1], &[1, 1, 1]).unwrap();
-        batch.commit().unwrap();
+        let pairs = (0..100u64).map(|i| (i.to_be_bytes().to_vec(), vec![i as u8]));
+        store.bulk_load(pairs.clone()).unwrap();
+        assert_eq!(Some(vec![42]), store.get(&42u64.to_be_bytes()).unwrap());
+        assert_eq!(pairs.collect::<Vec<_>>(), store.scan(&[], 1000).unwrap());
+        store.bulk_load(Vec::new().into_iter()).unwrap();
+        drop(store);
 
-        assert_eq!(Some(vec![0, 0, 0]), store.get(&[0, 0]).unwrap());
-        assert_eq!(Some(vec![1, 1, 1]), store.get(&[1, 1]).unwrap());
-        assert_eq!(None, store.get(&[2, 2]).unwrap());
+        let store = OptimisticStore::new(tmp_dir.path().to_str().unwrap());
+        store
+            .bulk_load((100..200u64).map(|i| (i.to_be_bytes().to_vec(), vec![i as u8])))
+            .unwrap();
+        assert_eq!(200, store.scan(&[], 1000).unwrap().len());
     }
 
     #[test]
-    fn exists() {
-        let tmp_dir = tempfile::Builder::new().prefix("exists").tempdir().unwrap();
-        let store = Store::new(tmp_dir.path().to_str().unwrap());
-        assert!(!store.exists(&[0, 0]).unwrap());
-
-        let mut batch = store.batch().unwrap();
-        batch.put(&[0, 0], &[0, 0, 0]).unwrap();
-        batch.commit().unwrap();
+    fn preset() {
+        let tmp_dir = tempfile::Builder::new().prefix("preset").tempdir().unwrap();
+        let path = tmp_dir.path().to_str().unwrap();
+        assert!(Store::open(path, &serde_json::json!({"compression": "lz4hc"})).is_err());
+        let options = serde_json::json!({
+            "memtable_size": 1 << 20,
+            "compression": "zstd",
+            "bloom_bits_per_key": 16,
+            "block_size": 16384
+        });
+        let store = Store::open(path, &options).unwrap();
+        store
+            .bulk_load((0..100u64).map(|i| (i.to_be_bytes().to_vec(), vec![i as u8])))
+            .unwrap();
+        assert_eq!(Some(vec![42]), store.get(&42u64.to_be_bytes()).unwrap());
+        drop(store);
 
-        assert!(store.exists(&[0, 0]).unwrap());
+        // the transaction databases take the same options
+        assert!(OptimisticStore::open(path, &serde_json::json!({"block": 1})).is_err());
+        let store = PessimisticStore::open(path, &options).unwrap();
+        assert_eq!(Some(vec![42]), store.get(&42u64.to_be_bytes()).unwrap());
     }
 
     #[test]
-    fn delete() {
-        let tmp_dir = tempfile::Builder::new().prefix("delete").tempdir().unwrap();
-        let store = Store::new(tmp_dir.path().to_str().unwrap());
-        let mut batch = store.batch().unwrap();
-        batch.put(&[0, 0], &[0, 0, 0]).unwrap();
-        batch.commit().unwrap();
-        assert_eq!(Some(vec![0, 0, 0]), store.get(&[0, 0]).unwrap());
-
-        let mut batch = store.batch().unwrap();
-        batch.delete(&[0, 0]).unwrap();
-        batch.commit().unwrap();
-        assert_eq!(None, store.get(&[0, 0]).unwrap());
+    fn transaction_conflict() {
+        let tmp_dir = tempfile::Builder::new()
+            .prefix("transaction_conflict")
+            .tempdir()
+            .unwrap();
+        let store = OptimisticStore::new(tmp_dir.path().to_str().unwrap());
+        let mut txn = store.transaction().unwrap();
+        txn.put(&[0, 0], &[0, 0, 0]).unwrap();
+        assert_eq!(Some(vec![0, 0, 0]), txn.get(&[0, 0]).unwrap());
+        txn.commit().unwrap();
+
+        let mut first = store.transaction().unwrap();
+        let mut second = store.transaction().unwrap();
+        assert_eq!(Some(vec![0, 0, 0]), first.get(&[0, 0]).unwrap());
+        assert_eq!(Some(vec![0, 0, 0]), second.get(&[0, 0]).unwrap());
+        second.put(&[0, 0], &[2, 2, 2]).unwrap();
+        second.commit().unwrap();
+        first.put(&[0, 0], &[1, 1, 1]).unwrap();
+        match first.commit() {
+            Err(Error::ConflictError(_)) => {}
+            result => panic!("expected a conflict, got {:?}", result),
+        }
+        assert_eq!(Some(vec![2, 2, 2]), store.get(&[0, 0]).unwrap());
     }
 }
@@ -1,58 +1,260 @@
-use crate::{Batch, Error, KeyValueStore};
-use sled::{open, Db};
+use crate::buffered::Writes;
+use crate::{
+    counter, Batch, Error, KeyValue, KeyValueStore, Snapshot, Transaction, TransactionalStore,
+};
+use sled::transaction::{abort, TransactionError};
+use sled::{open, Db, IVec};
+use std::collections::BTreeMap;
 use std::sync::Arc;
 
+// bytes of keys and values in each batch of a bulk load
+const BULK_LOAD_BATCH_SIZE: usize = 64 << 20;
+
 pub struct Store {
     db: Arc<Db>,
 }
 
 impl<'a> KeyValueStore<'a> for Store {
     type Batch = SledBatch;
+    type Snapshot = SledSnapshot;
 
     fn new(path: &str) -> Self {
-        let db = Arc::new(open(path).expect("Failed to open sled"));
-        Self { db }
+        Self::from_db(open(path).expect("Failed to open sled"))
     }
 
     fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
         self.db
-            .get(&key)
+            .get(key)
             .map(|v| v.map(|vi| vi.to_vec()))
             .map_err(Into::into)
     }
 
+    // sled has no batched read
+    fn multi_get(&self, keys: &[&[u8]]) -> Result<Vec<Option<Vec<u8>>>, Error> {
+        keys.iter().map(|key| self.get(key)).collect()
+    }
+
     fn exists(&self, key: &[u8]) -> Result<bool, Error> {
-        self.db.get(&key).map(|v| v.is_some()).map_err(Into::into)
+        self.db.get(key).map(|v| v.is_some()).map_err(Into::into)
+    }
+
+    fn scan(&self, start: &[u8], count: usize) -> Result<Vec<KeyValue>, Error> {
+        self.db
+            .range(start..)
+            .take(count)
+            .map(|item| {
+                item.map(|(key, value)| (key.to_vec(), value.to_vec()))
+                    .map_err(Into::into)
+            })
+            .collect()
     }
 
     fn batch(&self) -> Result<Self::Batch, Error> {
         Ok(Self::Batch {
             db: Arc::clone(&self.db),
             batch: sled::Batch::default(),
+            keys: Vec::new(),
+            ranged: None,
         })
     }
+
+    // sled has no import of sorted pairs, they are put by large batches
+    fn bulk_load<I: Iterator<Item = KeyValue>>(&self, pairs: I) -> Result<(), Error> {
+        let mut batch = sled::Batch::default();
+        let mut size = 0;
+        for (key, value) in pairs {
+            size += key.len() + value.len();
+            batch.insert(key, value);
+            if size >= BULK_LOAD_BATCH_SIZE {
+                self.db.apply_batch(std::mem::take(&mut batch))?;
+                size = 0;
+            }
+        }
+        if size > 0 {
+            self.db.apply_batch(batch)?;
+        }
+        Ok(())
+    }
+
+    // sled has no point-in-time view, reading the latest state wouldn't measure a snapshot
+    fn snapshot(&'a self) -> Result<Self::Snapshot, Error> {
+        Err(Error::DBError("sled has no snapshots".to_string()))
+    }
+
+    fn compare_and_swap(
+        &self,
+        key: &[u8],
+        old: Option<&[u8]>,
+        new: Option<&[u8]>,
+    ) -> Result<bool, Error> {
+        Ok(self.db.compare_and_swap(key, old, new)?.is_ok())
+    }
+
+    fn add(&self, key: &[u8], delta: u64) -> Result<(), Error> {
+        self.db.merge(key, delta.to_le_bytes())?;
+        Ok(())
+    }
+}
+
+impl Store {
+    fn from_db(db: Db) -> Self {
+        db.set_merge_operator(add_operand);
+        Self { db: Arc::new(db) }
+    }
+}
+
+// the merge operator of `add`, registered when the store is opened
+fn add_operand(_key: &[u8], old: Option<&[u8]>, delta: &[u8]) -> Option<Vec<u8>> {
+    let sum = counter(old).wrapping_add(counter(Some(delta)));
+    Some(sum.to_le_bytes().to_vec())
 }
 
 pub struct SledBatch {
     db: Arc<Db>,
     batch: sled::Batch,
+    // the keys put in `batch`, as it can't be read
+    keys: Vec<IVec>,
+    // the writes from the first range delete
+    ranged: Option<Writes>,
 }
 
 impl Batch for SledBatch {
     fn put(&mut self, key: &[u8], value: &[u8]) -> Result<(), Error> {
-        self.batch.insert(key, value);
+        match &mut self.ranged {
+            Some(writes) => writes.put(key, value),
+            None => {
+                let key = IVec::from(key);
+                self.keys.push(key.clone());
+                self.batch.insert(key, value);
+            }
+        }
         Ok(())
     }
 
     fn delete(&mut self, key: &[u8]) -> Result<(), Error> {
-        self.batch.remove(key);
+        match &mut self.ranged {
+            Some(writes) => writes.delete(key),
+            None => self.batch.remove(key),
+        }
         Ok(())
     }
 
+    fn delete_range(&mut self, start: &[u8], end: &[u8]) -> Result<(), Error> {
+        for key in &self.keys {
+            if start <= &key[..] && &key[..] < end {
+                self.batch.remove(key.clone());
+            }
+        }
+        self.ranged
+            .get_or_insert_with(Writes::default)
+            .delete_range(start, end);
+        Ok(())
+    }
+
+    // sled has no range delete, the keys of a range are removed one by one
     fn commit(self) -> Result<(), Error> {
-        self.db.apply_batch(self.batch)?;
+        let mut batch = self.batch;
+        if let Some(writes) = self.ranged {
+            let db = &self.db;
+            let changes = writes.changes(|start, end| {
+                db.range(start..end)
+                    .keys()
+                    .map(|key| key.map(|key| key.to_vec()).map_err(Into::into))
+                    .collect()
+            })?;
+            for (key, value) in changes {
+                match value {
+                    Some(value) => batch.insert(key, value),
+                    None => batch.remove(key),
+                }
+            }
+        }
+        self.db.apply_batch(batch)?;
+        Ok(())
+    }
+}
+
+impl<'a> TransactionalStore<'a> for Store {
+    type Transaction = SledTransaction;
+
+    fn transaction(&'a self) -> Result<Self::Transaction, Error> {
+        Ok(SledTransaction {
+            db: Arc::clone(&self.db),
+            reads: Vec::new(),
+            writes: BTreeMap::new(),
+        })
+    }
+}
+
+// sled transactions are closures which are retried on conflicts, so the writes are buffered
+// and the reads are checked again in one sled transaction when committing
+pub struct SledTransaction {
+    db: Arc<Db>,
+    reads: Vec<(Vec<u8>, Option<IVec>)>,
+    // None for a delete
+    writes: BTreeMap<Vec<u8>, Option<Vec<u8>>>,
+}
+
+impl Transaction for SledTransaction {
+    fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        if let Some(value) = self.writes.get(key) {
+            return Ok(value.clone());
+        }
+        let value = self.db.get(key)?;
+        self.reads.push((key.to_vec(), value.clone()));
+        Ok(value.map(|v| v.to_vec()))
+    }
+
+    fn put(&mut self, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        self.writes.insert(key.to_vec(), Some(value.to_vec()));
+        Ok(())
+    }
+
+    fn delete(&mut self, key: &[u8]) -> Result<(), Error> {
+        self.writes.insert(key.to_vec(), None);
         Ok(())
     }
+
+    fn commit(self) -> Result<(), Error> {
+        let result = self.db.transaction(|tx| {
+            for (key, value) in &self.reads {
+                if tx.get(key)? != *value {
+                    return abort(());
+                }
+            }
+            for (key, value) in &self.writes {
+                match value {
+                    Some(value) => tx.insert(key.as_slice(), value.as_slice())?,
+                    None => tx.remove(key.as_slice())?,
+                };
+            }
+            Ok(())
+        });
+        match result {
+            Ok(()) => Ok(()),
+            Err(TransactionError::Abort(())) => Err(Error::ConflictError(
+                "a key read by the transaction was changed".to_string(),
+            )),
+            Err(TransactionError::Storage(e)) => Err(e.into()),
+        }
+    }
+}
+
+/// Never built, `Store::snapshot` always fails.
+pub enum SledSnapshot {}
+
+impl Snapshot for SledSnapshot {
+    fn get(&self, _key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        match *self {}
+    }
+
+    fn multi_get(&self, _keys: &[&[u8]]) -> Result<Vec<Option<Vec<u8>>>, Error> {
+        match *self {}
+    }
+
+    fn exists(&self, _key: &[u8]) -> Result<bool, Error> {
+        match *self {}
+    }
 }
 
 impl From<sled::Error> for Error {
@@ -64,50 +266,77 @@ impl From<sled::Error> for Error {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::conformance::Capabilities;
+    use std::thread;
+    use std::time::Duration;
     use tempfile;
 
+    // attempts to take the lock of the files, every 10 ms
+    const LOCK_ATTEMPTS: usize = 200;
+
+    fn capabilities() -> Capabilities {
+        Capabilities {
+            snapshots: false,
+            ..Capabilities::default()
+        }
+    }
+
+    // the jobs of the thread pool of sled can hold the files of a store a moment after it's
+    // dropped, so the checks which reopen a store wait for its lock
+    fn open_locked(path: &str) -> impl Fn() -> Store {
+        let path = path.to_string();
+        move || {
+            for _ in 0..LOCK_ATTEMPTS {
+                match open(&path) {
+                    Err(sled::Error::Io(e)) if e.to_string().contains("could not acquire lock") => {
+                        thread::sleep(Duration::from_millis(10))
+                    }
+                    result => return Store::from_db(result.unwrap()),
+                }
+            }
+            panic!("the lock of {} is still held", path)
+        }
+    }
+
+    crate::conformance_tests!(Store, open_locked, capabilities());
+    crate::conformance::model_tests!(Store, open_locked, capabilities());
+
     #[test]
-    fn put_and_get() {
+    fn bulk_load() {
         let tmp_dir = tempfile::Builder::new()
-            .prefix("put_and_get")
+            .prefix("bulk_load")
             .tempdir()
             .unwrap();
         let store = Store::new(tmp_dir.path().to_str().unwrap());
-        let mut batch = store.batch().unwrap();
-        batch.put(&[0, 0], &[0, 0, 0]).unwrap();
-        batch.put(&[1, 1], &[1, 1, 1]).unwrap();
-        batch.commit().unwrap();
-
-        assert_eq!(Some(vec![0, 0, 0]), store.get(&[0, 0]).unwrap());
-        assert_eq!(Some(vec![1, 1, 1]), store.get(&[1, 1]).unwrap());
-        assert_eq!(None, store.get(&[2, 2]).unwrap());
+        let pairs = (0..100u64).map(|i| (i.to_be_bytes().to_vec(), vec![i as u8]));
+        store.bulk_load(pairs.clone()).unwrap();
+        assert_eq!(Some(vec![42]), store.get(&42u64.to_be_bytes()).unwrap());
+        assert_eq!(pairs.collect::<Vec<_>>(), store.scan(&[], 1000).unwrap());
     }
 
     #[test]
-    fn exists() {
-        let tmp_dir = tempfile::Builder::new().prefix("exists").tempdir().unwrap();
+    fn transaction_conflict() {
+        let tmp_dir = tempfile::Builder::new()
+            .prefix("transaction_conflict")
+            .tempdir()
+            .unwrap();
         let store = Store::new(tmp_dir.path().to_str().unwrap());
-        assert!(!store.exists(&[0, 0]).unwrap());
-
-        let mut batch = store.batch().unwrap();
-        batch.put(&[0, 0], &[0, 0, 0]).unwrap();
-        batch.commit().unwrap();
+        let mut txn = store.transaction().unwrap();
+        txn.put(&[0, 0], &[0, 0, 0]).unwrap();
+        assert_eq!(Some(vec![0, 0, 0]), txn.get(&[0, 0]).unwrap());
+        txn.commit().unwrap();
 
-        assert!(store.exists(&[0, 0]).unwrap());
-    }
-
-    #[test]
-    fn delete() {
-        let tmp_dir = tempfile::Builder::new().prefix("delete").tempdir().unwrap();
-        let store = Store::new(tmp_dir.path().to_str().unwrap());
-        let mut batch = store.batch().unwrap();
-        batch.put(&[0, 0], &[0, 0, 0]).unwrap();
-        batch.commit().unwrap();
-        assert_eq!(Some(vec![0, 0, 0]), store.get(&[0, 0]).unwrap());
-
-        let mut batch = store.batch().unwrap();
-        batch.delete(&[0, 0]).unwrap();
-        batch.commit().unwrap();
-        assert_eq!(None, store.get(&[0, 0]).unwrap());
+        let mut first = store.transaction().unwrap();
+        let mut second = store.transaction().unwrap();
+        assert_eq!(Some(vec![0, 0, 0]), first.get(&[0, 0]).unwrap());
+        assert_eq!(Some(vec![0, 0, 0]), second.get(&[0, 0]).unwrap());
+        second.put(&[0, 0], &[2, 2, 2]).unwrap();
+        second.commit().unwrap();
+        first.put(&[0, 0], &[1, 1, 1]).unwrap();
+        match first.commit() {
+            Err(Error::ConflictError(_)) => {}
+            result => panic!("expected a conflict, got {:?}", result),
+        }
+        assert_eq!(Some(vec![2, 2, 2]), store.get(&[0, 0]).unwrap());
     }
 }
@@ -0,0 +1,394 @@
+use crate::{
+    counter, parse_options, Batch, Error, KeyValue, KeyValueStore, Snapshot, Transaction,
+    TransactionalStore,
+};
+use rusqlite::{params, Connection, OptionalExtension, TransactionBehavior};
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+const CREATE_TABLE: &str =
+    "CREATE TABLE IF NOT EXISTS kv (k BLOB PRIMARY KEY, v BLOB) WITHOUT ROWID";
+const GET: &str = "SELECT v FROM kv WHERE k = ?1";
+const EXISTS: &str = "SELECT 1 FROM kv WHERE k = ?1";
+const SCAN: &str = "SELECT k, v FROM kv WHERE k >= ?1 ORDER BY k LIMIT ?2";
+const PUT: &str = "INSERT OR REPLACE INTO kv (k, v) VALUES (?1, ?2)";
+const DELETE: &str = "DELETE FROM kv WHERE k = ?1";
+const DELETE_RANGE: &str = "DELETE FROM kv WHERE k >= ?1 AND k < ?2";
+// bytes of keys and values put by each transaction of a bulk load
+const BULK_LOAD_TRANSACTION_SIZE: usize = 64 << 20;
+
+/// Options of `--options`, e.g. `{"journal_mode":"delete","synchronous":"full"}`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SqliteOptions {
+    journal_mode: JournalMode,
+    synchronous: Synchronous,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum JournalMode {
+    Delete,
+    Truncate,
+    Persist,
+    Memory,
+    // the readers and the snapshots don't block the writer
+    #[default]
+    Wal,
+    Off,
+}
+
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Synchronous {
+    Off,
+    // in wal mode the commits are not fsynced, only the checkpoints
+    #[default]
+    Normal,
+    Full,
+    Extra,
+}
+
+impl JournalMode {
+    fn pragma(self) -> &'static str {
+        match self {
+            JournalMode::Delete => "DELETE",
+            JournalMode::Truncate => "TRUNCATE",
+            JournalMode::Persist => "PERSIST",
+            JournalMode::Memory => "MEMORY",
+            JournalMode::Wal => "WAL",
+            JournalMode::Off => "OFF",
+        }
+    }
+}
+
+impl Synchronous {
+    fn pragma(self) -> &'static str {
+        match self {
+            Synchronous::Off => "OFF",
+            Synchronous::Normal => "NORMAL",
+            Synchronous::Full => "FULL",
+            Synchronous::Extra => "EXTRA",
+        }
+    }
+}
+
+/// A single `(k, v)` table of SQLite, to compare the key-value stores with a relational
+/// baseline. The connection is shared by a lock, a snapshot opens its own connection.
+pub struct Store {
+    conn: Arc<Mutex<Connection>>,
+    file: PathBuf,
+    journal_mode: JournalMode,
+}
+
+impl Store {
+    fn lock(&self) -> MutexGuard<'_, Connection> {
+        self.conn.lock().unwrap()
+    }
+}
+
+impl<'a> KeyValueStore<'a> for Store {
+    type Batch = SqliteBatch;
+    type Snapshot = SqliteSnapshot;
+
+    fn new(path: &str) -> Self {
+        Self::open(path, &serde_json::Value::Null).expect("Failed to open sqlite")
+    }
+
+    // sqlite stores the database in one file, in the directory of the path
+    fn open(path: &str, options: &serde_json::Value) -> Result<Self, Error> {
+        let options: SqliteOptions = parse_options(options)?;
+        fs::create_dir_all(path)?;
+        let file = Path::new(path).join("data.sqlite");
+        let conn = Connection::open(&file)?;
+        conn.pragma_update(None, "journal_mode", options.journal_mode.pragma())?;
+        conn.pragma_update(None, "synchronous", options.synchronous.pragma())?;
+        conn.execute(CREATE_TABLE, [])?;
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+            file,
+            journal_mode: options.journal_mode,
+        })
+    }
+
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        get(&self.lock(), key)
+    }
+
+    // all the keys are read by one prepared statement
+    fn multi_get(&self, keys: &[&[u8]]) -> Result<Vec<Option<Vec<u8>>>, Error> {
+        let conn = self.lock();
+        keys.iter().map(|key| get(&conn, key)).collect()
+    }
+
+    fn exists(&self, key: &[u8]) -> Result<bool, Error> {
+        exists(&self.lock(), key)
+    }
+
+    fn scan(&self, start: &[u8], count: usize) -> Result<Vec<KeyValue>, Error> {
+        let conn = self.lock();
+        let mut statement = conn.prepare_cached(SCAN)?;
+        let rows = statement.query_map(params![start, count as i64], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })?;
+        rows.map(|row| row.map_err(Into::into)).collect()
+    }
+
+    fn batch(&self) -> Result<Self::Batch, Error> {
+        Ok(SqliteBatch {
+            conn: Arc::clone(&self.conn),
+            writes: Vec::new(),
+        })
+    }
+
+    fn bulk_load<I: Iterator<Item = KeyValue>>(&self, pairs: I) -> Result<(), Error> {
+        let mut conn = self.lock();
+        let mut pairs = pairs.peekable();
+        while pairs.peek().is_some() {
+            let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+            {
+                let mut statement = tx.prepare_cached(PUT)?;
+                let mut size = 0;
+                for (key, value) in pairs.by_ref() {
+                    statement.execute(params![key, value])?;
+                    size += key.len() + value.len();
+                    if size >= BULK_LOAD_TRANSACTION_SIZE {
+                        break;
+                    }
+                }
+            }
+            tx.commit()?;
+        }
+        Ok(())
+    }
+
+    // a read transaction on a second connection, only the wal mode lets the writer commit
+    // while it's open
+    fn snapshot(&'a self) -> Result<Self::Snapshot, Error> {
+        if self.journal_mode != JournalMode::Wal {
+            return Err(Error::DBError(
+                "a sqlite snapshot needs the wal journal mode".to_string(),
+            ));
+        }
+        let conn = Connection::open(&self.file)?;
+        conn.execute_batch("BEGIN")?;
+        // the read transaction starts with its first read
+        exists(&conn, &[])?;
+        Ok(SqliteSnapshot { conn })
+    }
+
+    fn compare_and_swap(
+        &self,
+        key: &[u8],
+        old: Option<&[u8]>,
+        new: Option<&[u8]>,
+    ) -> Result<bool, Error> {
+        let mut conn = self.lock();
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        if get(&tx, key)?.as_deref() != old {
+            return Ok(false);
+        }
+        match new {
+            Some(value) => tx.prepare_cached(PUT)?.execute(params![key, value])?,
+            None => tx.prepare_cached(DELETE)?.execute(params![key])?,
+        };
+        tx.commit()?;
+        Ok(true)
+    }
+
+    // the counter is a little-endian blob which sql can't add to, it's read and written
+    // in one transaction
+    fn add(&self, key: &[u8], delta: u64) -> Result<(), Error> {
+        let mut conn = self.lock();
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let sum = counter(get(&tx, key)?.as_deref()).wrapping_add(delta);
+        tx.prepare_cached(PUT)?
+            .execute(params![key, &sum.to_le_bytes()[..]])?;
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+// the transaction keeps the lock of the connection, so the transactions never conflict,
+// they wait for each other
+impl<'a> TransactionalStore<'a> for Store {
+    type Transaction = SqliteTransaction<'a>;
+
+    fn transaction(&'a self) -> Result<Self::Transaction, Error> {
+        let conn = self.lock();
+        conn.execute_batch("BEGIN IMMEDIATE")?;
+        Ok(SqliteTransaction {
+            conn,
+            committed: false,
+        })
+    }
+}
+
+fn get(conn: &Connection, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+    conn.prepare_cached(GET)?
+        .query_row(params![key], |row| row.get(0))
+        .optional()
+        .map_err(Into::into)
+}
+
+fn exists(conn: &Connection, key: &[u8]) -> Result<bool, Error> {
+    Ok(conn.prepare_cached(EXISTS)?.exists(params![key])?)
+}
+
+enum Write {
+    Put(Vec<u8>, Vec<u8>),
+    Delete(Vec<u8>),
+    DeleteRange(Vec<u8>, Vec<u8>),
+}
+
+// the writes are buffered and executed by one transaction when committing
+pub struct SqliteBatch {
+    conn: Arc<Mutex<Connection>>,
+    writes: Vec<Write>,
+}
+
+impl Batch for SqliteBatch {
+    fn put(&mut self, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        self.writes.push(Write::Put(key.to_vec(), value.to_vec()));
+        Ok(())
+    }
+
+    fn delete(&mut self, key: &[u8]) -> Result<(), Error> {
+        self.writes.push(Write::Delete(key.to_vec()));
+        Ok(())
+    }
+
+    fn delete_range(&mut self, start: &[u8], end: &[u8]) -> Result<(), Error> {
+        self.writes
+            .push(Write::DeleteRange(start.to_vec(), end.to_vec()));
+        Ok(())
+    }
+
+    fn commit(self) -> Result<(), Error> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        for write in self.writes {
+            match write {
+                Write::Put(key, value) => tx.prepare_cached(PUT)?.execute(params![key, value])?,
+                Write::Delete(key) => tx.prepare_cached(DELETE)?.execute(params![key])?,
+                Write::DeleteRange(start, end) => tx
+                    .prepare_cached(DELETE_RANGE)?
+                    .execute(params![start, end])?,
+            };
+        }
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+pub struct SqliteSnapshot {
+    conn: Connection,
+}
+
+impl Snapshot for SqliteSnapshot {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        get(&self.conn, key)
+    }
+
+    fn multi_get(&self, keys: &[&[u8]]) -> Result<Vec<Option<Vec<u8>>>, Error> {
+        keys.iter().map(|key| get(&self.conn, key)).collect()
+    }
+
+    fn exists(&self, key: &[u8]) -> Result<bool, Error> {
+        exists(&self.conn, key)
+    }
+}
+
+// rolled back when dropped without commit
+pub struct SqliteTransaction<'a> {
+    conn: MutexGuard<'a, Connection>,
+    committed: bool,
+}
+
+impl<'a> Transaction for SqliteTransaction<'a> {
+    fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        get(&self.conn, key)
+    }
+
+    fn put(&mut self, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        self.conn
+            .prepare_cached(PUT)?
+            .execute(params![key, value])?;
+        Ok(())
+    }
+
+    fn delete(&mut self, key: &[u8]) -> Result<(), Error> {
+        self.conn.prepare_cached(DELETE)?.execute(params![key])?;
+        Ok(())
+    }
+
+    fn commit(mut self) -> Result<(), Error> {
+        self.conn.execute_batch("COMMIT")?;
+        self.committed = true;
+        Ok(())
+    }
+}
+
+impl<'a> Drop for SqliteTransaction<'a> {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = self.conn.execute_batch("ROLLBACK");
+        }
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(e: rusqlite::Error) -> Error {
+        Error::DBError(e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile;
+
+    crate::conformance_tests!(Store);
+    crate::conformance::model_tests!(Store);
+
+    #[test]
+    fn transaction_rollback() {
+        let tmp_dir = tempfile::Builder::new()
+            .prefix("transaction_rollback")
+            .tempdir()
+            .unwrap();
+        let store = Store::new(tmp_dir.path().to_str().unwrap());
+        let mut tx = store.transaction().unwrap();
+        tx.put(&[0, 0], &[0, 0, 0]).unwrap();
+        assert_eq!(Some(vec![0, 0, 0]), tx.get(&[0, 0]).unwrap());
+        drop(tx);
+        assert_eq!(None, store.get(&[0, 0]).unwrap());
+
+        let mut tx = store.transaction().unwrap();
+        tx.put(&[0, 0], &[1, 1, 1]).unwrap();
+        tx.commit().unwrap();
+        assert_eq!(Some(vec![1, 1, 1]), store.get(&[0, 0]).unwrap());
+    }
+
+    #[test]
+    fn options() {
+        let tmp_dir = tempfile::Builder::new()
+            .prefix("options")
+            .tempdir()
+            .unwrap();
+        let path = tmp_dir.path().to_str().unwrap();
+        assert!(Store::open(path, &serde_json::json!({"journal_mode": "mmap"})).is_err());
+        let options = serde_json::json!({"journal_mode": "delete", "synchronous": "full"});
+        let store = Store::open(path, &options).unwrap();
+        let mut batch = store.batch().unwrap();
+        batch.put(&[0, 0], &[0, 0, 0]).unwrap();
+        batch.commit().unwrap();
+        assert!(store.snapshot().is_err());
+        drop(store);
+
+        let store = Store::new(path);
+        assert_eq!(Some(vec![0, 0, 0]), store.get(&[0, 0]).unwrap());
+    }
+}
@@ -0,0 +1,136 @@
+use crate::Error;
+use rand::distributions::Standard;
+use rand::Rng;
+use serde::de::{self, Deserializer};
+use serde::ser::Serializer;
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+// same as db_bench, values are made of pieces which can be compressed independently
+const PIECE_SIZE: usize = 100;
+
+/// Content of the generated values.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ValueGenerator {
+    // uniformly random bytes, incompressible
+    #[default]
+    Random,
+    // compressed size / raw size, e.g. 0.5 for values which compress to half of their size
+    CompressionRatio(#[serde(deserialize_with = "compression_ratio")] f64),
+    // path of a sample file, values are copied from random offsets of it
+    Corpus(Corpus),
+}
+
+impl ValueGenerator {
+    pub fn generate<R: Rng>(&self, rng: &mut R, len: usize) -> Vec<u8> {
+        match self {
+            ValueGenerator::Random => rng.sample_iter(&Standard).take(len).collect(),
+            ValueGenerator::CompressionRatio(ratio) => {
+                let raw_len = ((PIECE_SIZE as f64 * ratio).round() as usize).clamp(1, PIECE_SIZE);
+                let mut value = Vec::with_capacity(len);
+                while value.len() < len {
+                    let raw: Vec<u8> = rng.sample_iter(&Standard).take(raw_len).collect();
+                    let piece_len = PIECE_SIZE.min(len - value.len());
+                    value.extend(raw.iter().cycle().take(piece_len));
+                }
+                value
+            }
+            ValueGenerator::Corpus(corpus) => corpus.generate(rng, len),
+        }
+    }
+}
+
+fn compression_ratio<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    let ratio = f64::deserialize(deserializer)?;
+    if ratio > 0.0 && ratio <= 1.0 {
+        Ok(ratio)
+    } else {
+        Err(de::Error::custom("compression_ratio needs 0 < ratio <= 1"))
+    }
+}
+
+/// Sample data for the values, serialized as the path of the file it was loaded from.
+#[derive(Debug, PartialEq)]
+pub struct Corpus {
+    path: String,
+    data: Vec<u8>,
+}
+
+impl Corpus {
+    pub fn load(path: &str) -> Result<Self, Error> {
+        let data = fs::read(path)?;
+        if data.is_empty() {
+            return Err(Error::WorkloadError(format!("empty corpus: {}", path)));
+        }
+        Ok(Corpus {
+            path: path.to_string(),
+            data,
+        })
+    }
+
+    pub fn generate<R: Rng>(&self, rng: &mut R, len: usize) -> Vec<u8> {
+        let offset = rng.gen_range(0, self.data.len());
+        self.data[offset..]
+            .iter()
+            .chain(self.data.iter().cycle())
+            .take(len)
+            .cloned()
+            .collect()
+    }
+}
+
+impl Serialize for Corpus {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.path)
+    }
+}
+
+impl<'de> Deserialize<'de> for Corpus {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let path = String::deserialize(deserializer)?;
+        Corpus::load(&path).map_err(|e| de::Error::custom(format!("{:?}", e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::thread_rng;
+    use std::collections::HashSet;
+
+    #[test]
+    fn compression_ratio() {
+        let values = ValueGenerator::CompressionRatio(0.25);
+        let value = values.generate(&mut thread_rng(), 1050);
+        assert_eq!(1050, value.len());
+        for piece in value.chunks(PIECE_SIZE) {
+            for i in 25..piece.len() {
+                assert_eq!(piece[i], piece[i - 25]);
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_compression_ratio() {
+        for ratio in &["0", "-0.5", "1.5", "1e400"] {
+            let json = format!(r#"{{"compression_ratio":{}}}"#, ratio);
+            assert!(serde_json::from_str::<ValueGenerator>(&json).is_err());
+        }
+        assert_eq!(
+            ValueGenerator::CompressionRatio(1.0),
+            serde_json::from_str(r#"{"compression_ratio":1}"#).unwrap()
+        );
+    }
+
+    #[test]
+    fn corpus_wraps_around() {
+        let corpus = Corpus {
+            path: String::new(),
+            data: vec![0, 1, 2],
+        };
+        let value = corpus.generate(&mut thread_rng(), 10);
+        assert_eq!(10, value.len());
+        assert_eq!(3, value.iter().collect::<HashSet<_>>().len());
+    }
+}
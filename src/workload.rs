@@ -1,120 +1,604 @@
-use crate::{Batch, KeyValueStore};
-use rand::distributions::Standard;
-use rand::seq::SliceRandom;
-use rand::{thread_rng, Rng};
+use crate::distribution::SizeGenerator;
+use crate::key::{KeyGenerator, ReadGenerator};
+use crate::value::ValueGenerator;
+use crate::{counter, Batch, Error, KeyValueStore, Snapshot, Transaction, TransactionalStore};
+use rand::rngs::StdRng;
+use rand::seq::{index, SliceRandom};
+use rand::{thread_rng, Rng, SeedableRng};
+use rand_pcg::Pcg32;
 use serde::{Deserialize, Serialize};
 use statrs::statistics::OrderStatistics;
-use std::time::Instant;
+use std::thread;
+use std::time::{Duration, Instant};
 
-pub fn generate_workload(task_generator: &TaskGenerator, nums_task: usize) -> Workload {
-    let mut rng = thread_rng();
-    Workload(
-        (0..nums_task)
-            .map(|_| match task_generator {
-                TaskGenerator::Get(key_size) => Task::Get(rand_vec(&mut rng, *key_size)),
-                TaskGenerator::Exists(key_size) => Task::Exists(rand_vec(&mut rng, *key_size)),
-                TaskGenerator::Batch(ogs) => Task::Batch(
-                    ogs.iter()
-                        .map(|og| match og {
-                            BatchOperationGenerator::Put(key_size, value_size) => {
-                                BatchOperation::Put(rand_vec(&mut rng, *key_size), *value_size)
-                            }
-                            BatchOperationGenerator::Delete(key_size) => {
-                                BatchOperation::Delete(rand_vec(&mut rng, *key_size))
-                            }
-                        })
-                        .collect(),
-                ),
-            })
-            .collect(),
-    )
-}
+// an increment by compare and swap gives up after this many swaps of a changed counter
+const SWAP_ATTEMPTS: usize = 100;
 
-pub fn sample_workload(
-    workload: &Workload,
+/// The tasks of `task_generator`, fails when it updates keys, which need the keys of an
+/// existing workload, see `sample_workload`.
+pub fn generate_workload(
     task_generator: &TaskGenerator,
     nums_task: usize,
-) -> Workload {
-    let mut rng = thread_rng();
-    let mut keys = Vec::new();
-    workload.0.iter().for_each(|task| match task {
-        Task::Batch(ops) => ops.iter().for_each(|op| match op {
-            BatchOperation::Put(key, _) => {
-                keys.push(key);
+    seed: u64,
+) -> Result<impl Iterator<Item = Task> + '_, Error> {
+    check_generated(task_generator)?;
+    check_ranges(task_generator)?;
+    let keys_per_task = task_generator.keys_per_task();
+    Ok((0..nums_task).map(move |index| {
+        let seq = (index * keys_per_task) as u64;
+        generate_task(task_generator, seq, &mut task_rng(seed, index))
+    }))
+}
+
+fn check_generated(task_generator: &TaskGenerator) -> Result<(), Error> {
+    if task_generator.updates_keys() {
+        Err(Error::WorkloadError(
+            "updates and read-modify-writes need existing keys, sample them from a workload"
+                .to_string(),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+// a range delete of random keys would silently delete nothing
+fn check_ranges(task_generator: &TaskGenerator) -> Result<(), Error> {
+    if let TaskGenerator::Batch(ogs) = task_generator {
+        for og in ogs {
+            if let BatchOperationGenerator::DeleteRange(key_generator, _) = og {
+                if key_generator.first_range(0).is_none() {
+                    return Err(Error::WorkloadError(format!(
+                        "delete_range needs keys ordered by a sequential segment: {:?}",
+                        key_generator
+                    )));
+                }
             }
-            _ => {}
-        }),
-        _ => {}
-    });
-    Workload(
-        (0..nums_task)
-            .map(|_| match task_generator {
-                TaskGenerator::Get(_) => Task::Get(keys.choose(&mut rng).unwrap().to_vec()),
-                TaskGenerator::Exists(_) => Task::Exists(keys.choose(&mut rng).unwrap().to_vec()),
-                TaskGenerator::Batch(ogs) => Task::Batch(
-                    ogs.iter()
-                        .map(|og| match og {
-                            BatchOperationGenerator::Put(key_size, value_size) => {
-                                BatchOperation::Put(rand_vec(&mut rng, *key_size), *value_size)
-                            }
-                            BatchOperationGenerator::Delete(_) => {
-                                BatchOperation::Delete(keys.choose(&mut rng).unwrap().to_vec())
-                            }
-                        })
-                        .collect(),
-                ),
-            })
-            .collect(),
-    )
+        }
+    }
+    Ok(())
+}
+
+/// The tasks of `task_generator` on the keys of an existing workload, fails when it
+/// deletes a range of keys which aren't ordered by their sequence numbers.
+pub fn sample_workload<'a, K: KeySet>(
+    keys: &'a K,
+    task_generator: &'a TaskGenerator,
+    nums_task: usize,
+    seed: u64,
+) -> Result<impl Iterator<Item = Task> + 'a, Error> {
+    check_ranges(task_generator)?;
+    let mut rng = StdRng::seed_from_u64(seed);
+    let keys_per_task = task_generator.keys_per_task();
+    // new keys continue the sequence of the sampled workload
+    let first_seq = keys.next_seq();
+    Ok((0..nums_task).map(move |index| match task_generator {
+        TaskGenerator::Get(read) => {
+            Task::Get(sample_read(keys, read, first_seq + index as u64, &mut rng))
+        }
+        TaskGenerator::Exists(read) => {
+            Task::Exists(sample_read(keys, read, first_seq + index as u64, &mut rng))
+        }
+        TaskGenerator::Scan(read, count) => Task::Scan(
+            sample_read(keys, read, first_seq + index as u64, &mut rng),
+            *count,
+        ),
+        TaskGenerator::MultiGet(read, count) => Task::MultiGet(
+            (0..*count)
+                .map(|position| {
+                    let seq = first_seq + (index * keys_per_task + position) as u64;
+                    sample_read(keys, read, seq, &mut rng)
+                })
+                .collect(),
+        ),
+        TaskGenerator::ReadModifyWrite(value_size) => {
+            Task::ReadModifyWrite(keys.choose(&mut rng), value_size.sample(&mut rng))
+        }
+        TaskGenerator::Increment(hot_keys, method) => {
+            Task::Increment(counter_key(&mut rng, *hot_keys), *method)
+        }
+        TaskGenerator::Batch(ogs) => Task::Batch(
+            ogs.iter()
+                .enumerate()
+                .map(|(position, og)| match og {
+                    BatchOperationGenerator::Put(key_generator, value_size) => {
+                        let seq = first_seq + (index * keys_per_task + position) as u64;
+                        BatchOperation::Put(
+                            key_generator.generate(&mut rng, seq),
+                            value_size.sample(&mut rng),
+                        )
+                    }
+                    BatchOperationGenerator::Update(value_size) => {
+                        BatchOperation::Put(keys.choose(&mut rng), value_size.sample(&mut rng))
+                    }
+                    BatchOperationGenerator::Delete(_) => {
+                        BatchOperation::Delete(keys.choose(&mut rng))
+                    }
+                    BatchOperationGenerator::DeleteRange(key_generator, count) => {
+                        prune(key_generator, *count)
+                    }
+                })
+                .collect(),
+        ),
+    }))
+}
+
+// the updates were rejected by `check_generated`
+fn generate_task<R: Rng>(task_generator: &TaskGenerator, seq: u64, rng: &mut R) -> Task {
+    match task_generator {
+        TaskGenerator::Get(read) => Task::Get(read.key.generate(rng, seq)),
+        TaskGenerator::Exists(read) => Task::Exists(read.key.generate(rng, seq)),
+        TaskGenerator::Scan(read, count) => Task::Scan(read.key.generate(rng, seq), *count),
+        TaskGenerator::MultiGet(read, count) => Task::MultiGet(
+            (0..*count)
+                .map(|position| read.key.generate(rng, seq + position as u64))
+                .collect(),
+        ),
+        TaskGenerator::ReadModifyWrite(_) => unreachable!(),
+        TaskGenerator::Increment(hot_keys, method) => {
+            Task::Increment(counter_key(rng, *hot_keys), *method)
+        }
+        TaskGenerator::Batch(ogs) => Task::Batch(
+            ogs.iter()
+                .enumerate()
+                .map(|(position, og)| {
+                    let seq = seq + position as u64;
+                    match og {
+                        BatchOperationGenerator::Put(key_generator, value_size) => {
+                            BatchOperation::Put(
+                                key_generator.generate(rng, seq),
+                                value_size.sample(rng),
+                            )
+                        }
+                        BatchOperationGenerator::Delete(key_generator) => {
+                            BatchOperation::Delete(key_generator.generate(rng, seq))
+                        }
+                        BatchOperationGenerator::DeleteRange(key_generator, count) => {
+                            prune(key_generator, *count)
+                        }
+                        BatchOperationGenerator::Update(_) => unreachable!(),
+                    }
+                })
+                .collect(),
+        ),
+    }
+}
+
+// the range of the first `count` sequence numbers, which are the oldest blocks, the key
+// generators without such a range were rejected by `check_ranges`
+fn prune(key_generator: &KeyGenerator, count: u64) -> BatchOperation {
+    let (start, end) = key_generator
+        .first_range(count)
+        .expect("the range deletes were checked");
+    BatchOperation::DeleteRange(start, end)
+}
+
+// the counters are the big-endian indexes of the hot keys, as in `run_contention`
+fn counter_key<R: Rng>(rng: &mut R, hot_keys: usize) -> Vec<u8> {
+    (rng.gen_range(0, hot_keys) as u64).to_be_bytes().to_vec()
+}
+
+// a missed read looks up a new key of the read generator, after all the keys of the workload
+fn sample_read<K: KeySet, R: Rng>(
+    keys: &K,
+    read: &ReadGenerator,
+    seq: u64,
+    rng: &mut R,
+) -> Vec<u8> {
+    if read.miss(rng) {
+        read.key.generate(rng, seq)
+    } else {
+        keys.choose(rng)
+    }
+}
+
+// every task has its own rng, so any task of a seeded workload can be regenerated by its index
+fn task_rng(seed: u64, index: usize) -> Pcg32 {
+    Pcg32::seed_from_u64(seed ^ (index as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15))
 }
 
-pub fn run_workload<'a, T: KeyValueStore<'a>>(workload: &Workload, store: &T) -> WorkloadResult {
+/// Keys which were put by a workload, sampled by the read and delete tasks.
+pub trait KeySet {
+    fn choose<R: Rng>(&self, rng: &mut R) -> Vec<u8>;
+    // the first key sequence number which is not used by the workload
+    fn next_seq(&self) -> u64;
+}
+
+/// Put keys collected from a materialized workload.
+pub struct CollectedKeys {
+    keys: Vec<Vec<u8>>,
+    next_seq: u64,
+}
+
+impl CollectedKeys {
+    pub fn from_tasks<I: IntoIterator<Item = Task>>(tasks: I) -> Self {
+        let mut keys = Vec::new();
+        let mut next_seq = 0;
+        for task in tasks {
+            match task {
+                Task::Get(_)
+                | Task::Exists(_)
+                | Task::Scan(..)
+                | Task::ReadModifyWrite(..)
+                | Task::Increment(..) => next_seq += 1,
+                Task::MultiGet(read_keys) => next_seq += read_keys.len() as u64,
+                Task::Batch(ops) => {
+                    next_seq += ops.len() as u64;
+                    for op in ops {
+                        if let BatchOperation::Put(key, _) = op {
+                            keys.push(key);
+                        }
+                    }
+                }
+                Task::Values(_) => {}
+            }
+        }
+        CollectedKeys { keys, next_seq }
+    }
+}
+
+impl KeySet for CollectedKeys {
+    fn choose<R: Rng>(&self, rng: &mut R) -> Vec<u8> {
+        self.keys
+            .choose(rng)
+            .expect("no put keys in workload")
+            .to_vec()
+    }
+
+    fn next_seq(&self) -> u64 {
+        self.next_seq
+    }
+}
+
+/// Put keys of a seeded workload, regenerated on demand instead of kept in memory.
+pub struct GeneratedKeys<'a> {
+    task_generator: &'a TaskGenerator,
+    nums_task: usize,
+    seed: u64,
+    put_positions: Vec<usize>,
+}
+
+impl<'a> GeneratedKeys<'a> {
+    pub fn new(
+        task_generator: &'a TaskGenerator,
+        nums_task: usize,
+        seed: u64,
+    ) -> Result<Self, Error> {
+        check_generated(task_generator)?;
+        let put_positions = match task_generator {
+            TaskGenerator::Batch(ogs) => ogs
+                .iter()
+                .enumerate()
+                .filter_map(|(i, og)| match og {
+                    BatchOperationGenerator::Put(..) => Some(i),
+                    _ => None,
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+        Ok(GeneratedKeys {
+            task_generator,
+            nums_task,
+            seed,
+            put_positions,
+        })
+    }
+}
+
+impl<'a> KeySet for GeneratedKeys<'a> {
+    fn choose<R: Rng>(&self, rng: &mut R) -> Vec<u8> {
+        assert!(
+            self.nums_task > 0 && !self.put_positions.is_empty(),
+            "no put keys in workload"
+        );
+        let index = rng.gen_range(0, self.nums_task);
+        let position = *self.put_positions.choose(rng).unwrap();
+        let seq = (index * self.task_generator.keys_per_task()) as u64;
+        match generate_task(self.task_generator, seq, &mut task_rng(self.seed, index)) {
+            Task::Batch(mut ops) => match ops.swap_remove(position) {
+                BatchOperation::Put(key, _) => key,
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        }
+    }
+
+    fn next_seq(&self) -> u64 {
+        (self.nums_task * self.task_generator.keys_per_task()) as u64
+    }
+}
+
+pub fn run_workload<'a, T: KeyValueStore<'a>, I: IntoIterator<Item = Task>>(
+    tasks: I,
+    store: &T,
+) -> WorkloadResult {
     let mut rng = thread_rng();
+    let mut values = ValueGenerator::default();
     WorkloadResult(
-        workload
-            .0
-            .iter()
-            .map(|task| match task {
+        tasks
+            .into_iter()
+            .filter_map(|task| match task {
                 Task::Get(key) => {
                     let now = Instant::now();
-                    store.get(key).expect("store get failed");
+                    let value = store.get(&key).expect("store get failed");
                     let elapsed = now.elapsed().as_nanos();
-                    TaskResult(TaskType::Get, elapsed)
+                    Some(TaskResult(TaskType::Get, elapsed, Some(value.is_some())))
+                }
+                Task::MultiGet(keys) => {
+                    let keys = keys.iter().map(|key| &key[..]).collect::<Vec<_>>();
+                    let now = Instant::now();
+                    let values = store.multi_get(&keys).expect("store multi_get failed");
+                    let elapsed = now.elapsed().as_nanos();
+                    let found = values.iter().all(|value| value.is_some());
+                    Some(TaskResult(TaskType::MultiGet, elapsed, Some(found)))
                 }
                 Task::Exists(key) => {
                     let now = Instant::now();
-                    store.exists(key).expect("store exists failed");
+                    let exists = store.exists(&key).expect("store exists failed");
+                    let elapsed = now.elapsed().as_nanos();
+                    Some(TaskResult(TaskType::Exists, elapsed, Some(exists)))
+                }
+                Task::Scan(start, count) => {
+                    let now = Instant::now();
+                    let pairs = store.scan(&start, count).expect("store scan failed");
                     let elapsed = now.elapsed().as_nanos();
-                    TaskResult(TaskType::Exists, elapsed)
+                    Some(TaskResult(
+                        TaskType::Scan,
+                        elapsed,
+                        Some(pairs.len() == count),
+                    ))
                 }
                 Task::Batch(operations) => {
                     let mut batch = store.batch().expect("failed to create batch");
                     operations.iter().for_each(|op| match op {
                         BatchOperation::Put(key, value_size) => {
-                            let value = rand_vec(&mut rng, *value_size);
+                            let value = values.generate(&mut rng, *value_size);
                             batch.put(key, &value).expect("batch put failed");
                         }
                         BatchOperation::Delete(key) => {
                             batch.delete(key).expect("batch delete failed");
                         }
+                        BatchOperation::DeleteRange(start, end) => {
+                            batch
+                                .delete_range(start, end)
+                                .expect("batch delete_range failed");
+                        }
                     });
                     let now = Instant::now();
                     batch.commit().expect("failed to commit");
                     let elapsed = now.elapsed().as_nanos();
-                    TaskResult(TaskType::Batch, elapsed)
+                    Some(TaskResult(TaskType::Batch, elapsed, None))
+                }
+                Task::ReadModifyWrite(key, value_size) => {
+                    let value = values.generate(&mut rng, value_size);
+                    let now = Instant::now();
+                    store.get(&key).expect("store get failed");
+                    let mut batch = store.batch().expect("failed to create batch");
+                    batch.put(&key, &value).expect("batch put failed");
+                    batch.commit().expect("failed to commit");
+                    let elapsed = now.elapsed().as_nanos();
+                    Some(TaskResult(TaskType::ReadModifyWrite, elapsed, None))
+                }
+                Task::Increment(key, method) => {
+                    let now = Instant::now();
+                    let swapped = match method {
+                        IncrementMethod::Add => {
+                            store.add(&key, 1).expect("store add failed");
+                            None
+                        }
+                        IncrementMethod::CompareAndSwap => Some((0..SWAP_ATTEMPTS).any(|_| {
+                            let old = store.get(&key).expect("store get failed");
+                            let new = (counter(old.as_deref()) + 1).to_le_bytes();
+                            store
+                                .compare_and_swap(&key, old.as_deref(), Some(&new))
+                                .expect("store compare_and_swap failed")
+                        })),
+                        IncrementMethod::ReadModifyWrite => {
+                            let old = store.get(&key).expect("store get failed");
+                            let new = (counter(old.as_deref()) + 1).to_le_bytes();
+                            let mut batch = store.batch().expect("failed to create batch");
+                            batch.put(&key, &new).expect("batch put failed");
+                            batch.commit().expect("failed to commit");
+                            None
+                        }
+                    };
+                    let elapsed = now.elapsed().as_nanos();
+                    Some(TaskResult(TaskType::Increment, elapsed, swapped))
+                }
+                Task::Values(generator) => {
+                    values = generator;
+                    None
                 }
             })
             .collect(),
     )
 }
 
-pub fn generate_report(result: &WorkloadResult) -> WorkloadReport {
+/// Runs the reads of a workload on a snapshot, the writes go on in another workload.
+pub fn run_snapshot_reads<S: Snapshot, I: IntoIterator<Item = Task>>(
+    tasks: I,
+    snapshot: &S,
+) -> WorkloadResult {
+    WorkloadResult(
+        tasks
+            .into_iter()
+            .map(|task| match task {
+                Task::Get(key) => {
+                    let now = Instant::now();
+                    let value = snapshot.get(&key).expect("snapshot get failed");
+                    let elapsed = now.elapsed().as_nanos();
+                    TaskResult(TaskType::Get, elapsed, Some(value.is_some()))
+                }
+                Task::MultiGet(keys) => {
+                    let keys = keys.iter().map(|key| &key[..]).collect::<Vec<_>>();
+                    let now = Instant::now();
+                    let values = snapshot
+                        .multi_get(&keys)
+                        .expect("snapshot multi_get failed");
+                    let elapsed = now.elapsed().as_nanos();
+                    let found = values.iter().all(|value| value.is_some());
+                    TaskResult(TaskType::MultiGet, elapsed, Some(found))
+                }
+                Task::Exists(key) => {
+                    let now = Instant::now();
+                    let exists = snapshot.exists(&key).expect("snapshot exists failed");
+                    let elapsed = now.elapsed().as_nanos();
+                    TaskResult(TaskType::Exists, elapsed, Some(exists))
+                }
+                task => panic!("snapshot can only read, unexpected task {:?}", task),
+            })
+            .collect(),
+    )
+}
+
+/// Fills the store with `nums_key` sequential keys by a bulk load, the same keys as
+/// `{"batch":[{"put":[{"sequential":key_size},value_size]}]}` puts, so that a generated
+/// workload can sample them.
+pub fn run_load<'a, T: KeyValueStore<'a>>(
+    store: &T,
+    nums_key: usize,
+    key_size: usize,
+    value_size: &SizeGenerator,
+    values: &ValueGenerator,
+    seed: u64,
+) -> LoadReport {
+    let keys = KeyGenerator::Sequential(key_size);
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut bytes = 0;
+    let mut generating = Duration::ZERO;
+    let now = Instant::now();
+    // the pairs are generated while loading, but only the store is timed, as for the tasks
+    // of `run_workload`
+    let pairs = (0..nums_key as u64).map(|seq| {
+        let start = Instant::now();
+        let key = keys.generate(&mut rng, seq);
+        let size = value_size.sample(&mut rng);
+        let value = values.generate(&mut rng, size);
+        bytes += key.len() + value.len();
+        generating += start.elapsed();
+        (key, value)
+    });
+    store.bulk_load(pairs).expect("bulk load failed");
+    let elapsed = (now.elapsed() - generating).as_secs_f64();
+    LoadReport {
+        keys: nums_key,
+        bytes,
+        elapsed,
+        keys_per_second: nums_key as f64 / elapsed,
+        mib_per_second: bytes as f64 / (1 << 20) as f64 / elapsed,
+    }
+}
+
+/// Increments hot counters from concurrent transactions, each of them reads some of the
+/// counters and puts them back incremented. A transaction which conflicts is retried.
+pub fn run_contention<'a, T: TransactionalStore<'a> + Sync>(
+    store: &'a T,
+    threads: usize,
+    nums_task: usize,
+    hot_keys: usize,
+    keys_per_transaction: usize,
+    seed: u64,
+) -> WorkloadResult {
+    assert!(
+        keys_per_transaction <= hot_keys,
+        "a transaction can't increment more counters than the hot keys"
+    );
+    let results = thread::scope(|scope| {
+        let workers = (0..threads)
+            .map(|thread_index| {
+                scope.spawn(move || {
+                    let mut rng = task_rng(seed, thread_index);
+                    let mut results = Vec::with_capacity(nums_task);
+                    for _ in 0..nums_task {
+                        // in order, so that two transactions which lock the same keys never
+                        // wait for each other in a deadlock
+                        let mut keys = index::sample(&mut rng, hot_keys, keys_per_transaction)
+                            .into_iter()
+                            .map(|i| (i as u64).to_be_bytes())
+                            .collect::<Vec<_>>();
+                        keys.sort();
+                        loop {
+                            let now = Instant::now();
+                            let committed = match increment(store, &keys) {
+                                Ok(()) => true,
+                                Err(Error::ConflictError(_)) => false,
+                                Err(e) => panic!("transaction failed: {:?}", e),
+                            };
+                            let elapsed = now.elapsed().as_nanos();
+                            results.push(TaskResult(
+                                TaskType::Transaction,
+                                elapsed,
+                                Some(committed),
+                            ));
+                            if committed {
+                                break;
+                            }
+                        }
+                    }
+                    results
+                })
+            })
+            .collect::<Vec<_>>();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap())
+            .collect()
+    });
+    WorkloadResult(results)
+}
+
+// the counters are little-endian u64, a missing counter is 0
+fn increment<'a, T: TransactionalStore<'a>>(store: &'a T, keys: &[[u8; 8]]) -> Result<(), Error> {
+    let mut txn = store.transaction()?;
+    for key in keys {
+        let count = counter(txn.get(key)?.as_deref());
+        txn.put(key, &(count + 1).to_le_bytes())?;
+    }
+    txn.commit()
+}
+
+// only the tasks of `task_type` are reported when it's given
+pub fn generate_report(result: &WorkloadResult, task_type: Option<TaskType>) -> WorkloadReport {
+    let selected = |tr: &TaskResult| task_type.is_none_or(|task_type| tr.0 == task_type);
+    // the transactions and the increments by compare and swap commit or abort
+    let abortable = |tr: &TaskResult| tr.0 == TaskType::Transaction || tr.0 == TaskType::Increment;
+    let lookup = |tr: &TaskResult| selected(tr) && !abortable(tr);
+    let lookups = result.0.iter().any(|tr| lookup(tr) && tr.2.is_some());
+    let transactions = result
+        .0
+        .iter()
+        .filter(|tr| selected(tr) && abortable(tr) && tr.2.is_some());
+    let (count, aborted) = transactions.fold((0, 0), |(count, aborted), tr| {
+        (count + 1, aborted + (tr.2 == Some(false)) as usize)
+    });
+    WorkloadReport {
+        all: latency(result, selected),
+        hit: if lookups {
+            Some(latency(result, |tr| lookup(tr) && tr.2 == Some(true)))
+        } else {
+            None
+        },
+        miss: if lookups {
+            Some(latency(result, |tr| lookup(tr) && tr.2 == Some(false)))
+        } else {
+            None
+        },
+        abort_rate: if count > 0 {
+            Some(aborted as f64 / count as f64)
+        } else {
+            None
+        },
+    }
+}
+
+fn latency<F: Fn(&TaskResult) -> bool>(result: &WorkloadResult, filter: F) -> Latency {
     let data = &mut result
         .0
         .iter()
+        .filter(|tr| filter(tr))
         .map(|tr| (tr.1 as f64) / 1000.0)
         .collect::<Vec<_>>()[..];
-    WorkloadReport {
+    Latency {
+        count: data.len(),
         total: data.iter().sum(),
         median: data.median(),
         lower_quartile: data.lower_quartile(),
@@ -122,65 +606,344 @@ pub fn generate_report(result: &WorkloadResult) -> WorkloadReport {
     }
 }
 
-fn rand_vec<R: Rng>(rng: &mut R, len: usize) -> Vec<u8> {
-    rng.sample_iter(&Standard).take(len).collect()
-}
-
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum TaskGenerator {
-    // key_size
-    Get(usize),
-    // key_size
-    Exists(usize),
+    Get(ReadGenerator),
+    Exists(ReadGenerator),
+    // keys, number of keys read at once
+    MultiGet(ReadGenerator, usize),
+    // start key, number of pairs read in key order
+    Scan(ReadGenerator, usize),
+    // value_size, reads an existing key and puts it back
+    ReadModifyWrite(SizeGenerator),
+    // hot_keys, increments one of the counters
+    Increment(usize, IncrementMethod),
     Batch(Vec<BatchOperationGenerator>),
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// How a counter is incremented, to compare the merge of the engines with reading it back.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
-pub enum BatchOperationGenerator {
-    // key_size, value_size
-    Put(usize, usize),
-    // key_size
-    Delete(usize),
+pub enum IncrementMethod {
+    // `add`, a merge operator on RocksDB and sled
+    Add,
+    // reads the counter and swaps it, again when it was changed in between
+    CompareAndSwap,
+    // reads the counter and puts it back in a batch, not atomic
+    ReadModifyWrite,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Workload(pub Vec<Task>);
+impl TaskGenerator {
+    // every operation of a task uses one key sequence number
+    pub fn keys_per_task(&self) -> usize {
+        match self {
+            TaskGenerator::Batch(ogs) => ogs.len(),
+            TaskGenerator::MultiGet(_, count) => *count,
+            _ => 1,
+        }
+    }
+
+    // whether the generator overwrites existing keys, which only a sampled workload has
+    pub fn updates_keys(&self) -> bool {
+        match self {
+            TaskGenerator::ReadModifyWrite(_) => true,
+            TaskGenerator::Batch(ogs) => ogs
+                .iter()
+                .any(|og| matches!(og, BatchOperationGenerator::Update(_))),
+            _ => false,
+        }
+    }
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
+pub enum BatchOperationGenerator {
+    // key, value_size
+    Put(KeyGenerator, SizeGenerator),
+    // value_size, overwrites an existing key
+    Update(SizeGenerator),
+    Delete(KeyGenerator),
+    // key, count, deletes the keys of the first `count` sequence numbers
+    DeleteRange(KeyGenerator, u64),
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Task {
     Get(Vec<u8>),
     Exists(Vec<u8>),
+    MultiGet(Vec<Vec<u8>>),
+    // start key, count
+    Scan(Vec<u8>, usize),
     Batch(Vec<BatchOperation>),
+    // key, value_size
+    ReadModifyWrite(Vec<u8>, usize),
+    Increment(Vec<u8>, IncrementMethod),
+    // sets the content of the values put by the following tasks
+    Values(ValueGenerator),
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum BatchOperation {
     Put(Vec<u8>, usize),
     Delete(Vec<u8>),
+    // start included, end excluded
+    DeleteRange(Vec<u8>, Vec<u8>),
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct WorkloadResult(pub Vec<TaskResult>);
 
 #[derive(Debug, Serialize, Deserialize)]
-pub struct TaskResult(TaskType, u128);
+// task type, elapsed nanos, whether the key was found by a lookup, all the keys for a multi_get,
+// all the pairs for a scan, whether a transaction committed, whether an increment by compare
+// and swap swapped before giving up
+pub struct TaskResult(TaskType, u128, Option<bool>);
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum TaskType {
     Get,
     Exists,
+    MultiGet,
+    Scan,
     Batch,
+    ReadModifyWrite,
+    Increment,
+    Transaction,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct WorkloadReport {
+    #[serde(flatten)]
+    all: Latency,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hit: Option<Latency>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    miss: Option<Latency>,
+    // aborted transactions / all the transactions, the retries included, or increments by
+    // compare and swap which gave up / all of them
+    #[serde(skip_serializing_if = "Option::is_none")]
+    abort_rate: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LoadReport {
+    keys: usize,
+    // keys and values
+    bytes: usize,
+    // seconds
+    elapsed: f64,
+    keys_per_second: f64,
+    mib_per_second: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Latency {
+    count: usize,
     total: f64,
     median: f64,
     lower_quartile: f64,
     upper_quartile: f64,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_keys_are_put_keys() {
+        let task_generator = TaskGenerator::Batch(vec![
+            BatchOperationGenerator::Put(
+                KeyGenerator::Random(SizeGenerator::Fixed(32)),
+                SizeGenerator::Fixed(16),
+            ),
+            BatchOperationGenerator::Delete(KeyGenerator::Random(SizeGenerator::Fixed(32))),
+            BatchOperationGenerator::Put(KeyGenerator::Sequential(8), SizeGenerator::Fixed(16)),
+        ]);
+        let collected =
+            CollectedKeys::from_tasks(generate_workload(&task_generator, 100, 7).unwrap());
+        let generated = GeneratedKeys::new(&task_generator, 100, 7).unwrap();
+        let mut rng = thread_rng();
+        for _ in 0..100 {
+            assert!(collected.keys.contains(&generated.choose(&mut rng)));
+        }
+        assert_eq!(300, collected.next_seq());
+        assert_eq!(300, generated.next_seq());
+    }
+
+    #[test]
+    fn seeded_workload_is_reproducible() {
+        let task_generator: TaskGenerator =
+            serde_json::from_str("{\"get\":{\"log_normal\":[5.0,1.0]}}").unwrap();
+        assert_eq!(
+            generate_workload(&task_generator, 10, 7)
+                .unwrap()
+                .collect::<Vec<_>>(),
+            generate_workload(&task_generator, 10, 7)
+                .unwrap()
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn updates_overwrite_existing_keys() {
+        let load: TaskGenerator = serde_json::from_str("{\"batch\":[{\"put\":[32,16]}]}").unwrap();
+        let keys = CollectedKeys::from_tasks(generate_workload(&load, 10, 7).unwrap());
+        let update: TaskGenerator =
+            serde_json::from_str("{\"batch\":[{\"update\":16},{\"delete\":32}]}").unwrap();
+        let read_modify_write: TaskGenerator =
+            serde_json::from_str("{\"read_modify_write\":16}").unwrap();
+        for task in sample_workload(&keys, &update, 10, 7)
+            .unwrap()
+            .chain(sample_workload(&keys, &read_modify_write, 10, 7).unwrap())
+        {
+            match task {
+                Task::Batch(ops) => match &ops[0] {
+                    BatchOperation::Put(key, 16) => assert!(keys.keys.contains(key)),
+                    op => panic!("unexpected operation {:?}", op),
+                },
+                Task::ReadModifyWrite(key, 16) => assert!(keys.keys.contains(&key)),
+                task => panic!("unexpected task {:?}", task),
+            }
+        }
+    }
+
+    #[test]
+    fn updates_are_not_generated() {
+        for json in &[
+            "{\"batch\":[{\"put\":[32,16]},{\"update\":16}]}",
+            "{\"read_modify_write\":16}",
+        ] {
+            let task_generator: TaskGenerator = serde_json::from_str(json).unwrap();
+            assert!(matches!(
+                generate_workload(&task_generator, 10, 7).err(),
+                Some(Error::WorkloadError(_))
+            ));
+            assert!(GeneratedKeys::new(&task_generator, 10, 7).is_err());
+        }
+    }
+
+    #[test]
+    fn multi_get_samples_put_keys() {
+        let load: TaskGenerator = serde_json::from_str("{\"batch\":[{\"put\":[32,16]}]}").unwrap();
+        let keys = CollectedKeys::from_tasks(generate_workload(&load, 10, 7).unwrap());
+        let multi_get: TaskGenerator = serde_json::from_str("{\"multi_get\":[32,4]}").unwrap();
+        assert_eq!(4, multi_get.keys_per_task());
+        for task in sample_workload(&keys, &multi_get, 10, 7).unwrap() {
+            match task {
+                Task::MultiGet(read_keys) => {
+                    assert_eq!(4, read_keys.len());
+                    assert!(read_keys.iter().all(|key| keys.keys.contains(key)));
+                }
+                task => panic!("unexpected task {:?}", task),
+            }
+        }
+    }
+
+    #[test]
+    fn prune_deletes_oldest_keys() {
+        let store = crate::memory::Store::new("");
+        let load: TaskGenerator =
+            serde_json::from_str("{\"batch\":[{\"put\":[{\"sequential\":8},16]}]}").unwrap();
+        let keys = CollectedKeys::from_tasks(generate_workload(&load, 10, 7).unwrap());
+        run_workload(generate_workload(&load, 10, 7).unwrap(), &store);
+        let prune: TaskGenerator =
+            serde_json::from_str("{\"batch\":[{\"delete_range\":[{\"sequential\":8},4]}]}")
+                .unwrap();
+        run_workload(sample_workload(&keys, &prune, 1, 7).unwrap(), &store);
+
+        let scan: TaskGenerator =
+            serde_json::from_str("{\"scan\":[{\"sequential\":8},3]}").unwrap();
+        let tasks = generate_workload(&scan, 1, 7).unwrap().collect::<Vec<_>>();
+        assert_eq!(vec![Task::Scan(0u64.to_be_bytes().to_vec(), 3)], tasks);
+        let pairs = store.scan(&0u64.to_be_bytes(), 3).unwrap();
+        let scanned = pairs.into_iter().map(|(key, _)| key).collect::<Vec<_>>();
+        let expected = (4..7u64)
+            .map(|i| i.to_be_bytes().to_vec())
+            .collect::<Vec<_>>();
+        assert_eq!(expected, scanned);
+    }
+
+    #[test]
+    fn prune_deletes_oldest_reverse_keys() {
+        for key in &[
+            "{\"reverse_sequential\":8}",
+            "{\"composite\":[{\"fixed\":[1]},{\"reverse_sequential\":8},{\"random\":4}]}",
+        ] {
+            let store = crate::memory::Store::new("");
+            let load: TaskGenerator =
+                serde_json::from_str(&format!("{{\"batch\":[{{\"put\":[{},16]}}]}}", key)).unwrap();
+            let keys = CollectedKeys::from_tasks(generate_workload(&load, 10, 7).unwrap());
+            run_workload(generate_workload(&load, 10, 7).unwrap(), &store);
+            let prune: TaskGenerator =
+                serde_json::from_str(&format!("{{\"batch\":[{{\"delete_range\":[{},4]}}]}}", key))
+                    .unwrap();
+            run_workload(sample_workload(&keys, &prune, 1, 7).unwrap(), &store);
+
+            let mut expected = generate_workload(&load, 10, 7)
+                .unwrap()
+                .skip(4)
+                .map(|task| match task {
+                    Task::Batch(mut ops) => match ops.remove(0) {
+                        BatchOperation::Put(key, _) => key,
+                        op => panic!("unexpected operation {:?}", op),
+                    },
+                    task => panic!("unexpected task {:?}", task),
+                })
+                .collect::<Vec<_>>();
+            expected.sort();
+            let pairs = store.scan(&[], 20).unwrap();
+            let scanned = pairs.into_iter().map(|(key, _)| key).collect::<Vec<_>>();
+            assert_eq!(expected, scanned);
+        }
+    }
+
+    #[test]
+    fn prune_rejects_random_keys() {
+        let load: TaskGenerator = serde_json::from_str("{\"batch\":[{\"put\":[32,16]}]}").unwrap();
+        let keys = CollectedKeys::from_tasks(generate_workload(&load, 10, 7).unwrap());
+        for key in &["32", "{\"composite\":[{\"random\":4},{\"sequential\":8}]}"] {
+            let prune: TaskGenerator =
+                serde_json::from_str(&format!("{{\"batch\":[{{\"delete_range\":[{},4]}}]}}", key))
+                    .unwrap();
+            assert!(generate_workload(&prune, 1, 7).is_err());
+            assert!(sample_workload(&keys, &prune, 1, 7).is_err());
+        }
+    }
+
+    #[test]
+    fn increments_count_every_task() {
+        let store = crate::memory::Store::new("");
+        for method in &["add", "compare_and_swap", "read_modify_write"] {
+            let increment: TaskGenerator =
+                serde_json::from_str(&format!("{{\"increment\":[3,\"{}\"]}}", method)).unwrap();
+            let result = run_workload(generate_workload(&increment, 50, 7).unwrap(), &store);
+            let report = generate_report(&result, None);
+            assert!(report.hit.is_none());
+            if *method == "compare_and_swap" {
+                assert_eq!(Some(0.0), report.abort_rate);
+            } else {
+                assert_eq!(None, report.abort_rate);
+            }
+        }
+        let total: u64 = (0..3u64)
+            .map(|i| counter(store.get(&i.to_be_bytes()).unwrap().as_deref()))
+            .sum();
+        assert_eq!(150, total);
+    }
+
+    #[test]
+    fn contention_counts_every_commit() {
+        let store = crate::memory::Store::new("");
+        let result = run_contention(&store, 4, 50, 3, 2, 7);
+        let commits = result.0.iter().filter(|tr| tr.2 == Some(true)).count();
+        assert_eq!(200, commits);
+        let total: u64 = (0..3u64)
+            .map(|i| counter(store.get(&i.to_be_bytes()).unwrap().as_deref()))
+            .sum();
+        assert_eq!(400, total);
+    }
+}
@@ -0,0 +1,68 @@
+#![cfg(feature = "plugin")]
+
+use rust_kvstore_bench::conformance::Capabilities;
+use rust_kvstore_bench::plugin::Store;
+use rust_kvstore_bench::{counter, Batch, KeyValueStore};
+use std::env::consts::{DLL_PREFIX, DLL_SUFFIX};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::OnceLock;
+
+// builds examples/memory_plugin once, in its own target directory so that it doesn't wait
+// for the lock of the one of these tests
+fn library() -> &'static Path {
+    static LIBRARY: OnceLock<PathBuf> = OnceLock::new();
+    LIBRARY.get_or_init(|| {
+        let target = Path::new(env!("CARGO_TARGET_TMPDIR")).join("memory_plugin");
+        let status = Command::new(env!("CARGO"))
+            .arg("build")
+            .arg("--manifest-path")
+            .arg(Path::new(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml"))
+            .args(["--package", "memory-plugin", "--target-dir"])
+            .arg(&target)
+            .status()
+            .expect("failed to run cargo");
+        assert!(status.success(), "failed to build the plugin");
+        target
+            .join("debug")
+            .join(format!("{}memory_plugin{}", DLL_PREFIX, DLL_SUFFIX))
+    })
+}
+
+fn open(options: serde_json::Value) -> Store {
+    let options = serde_json::json!({"library": library(), "options": options});
+    Store::open("", &options).unwrap()
+}
+
+// each store is a new in-memory store of the plugin
+fn open_plugin(_: &str) -> impl Fn() -> Store {
+    || open(serde_json::Value::Null)
+}
+
+rust_kvstore_bench::conformance_tests!(
+    Store,
+    open_plugin,
+    Capabilities {
+        persistent: false,
+        snapshots: false,
+        ..Capabilities::default()
+    }
+);
+
+#[test]
+fn load_and_call() {
+    let store = open(serde_json::Value::Null);
+    let mut batch = store.batch().unwrap();
+    batch.put(&[0, 0], &[0, 0, 0]).unwrap();
+    batch.delete_range(&[1], &[2]).unwrap();
+    batch.commit().unwrap();
+    assert_eq!(Some(vec![0, 0, 0]), store.get(&[0, 0]).unwrap());
+
+    store.add(&[1], 3).unwrap();
+    assert_eq!(3, counter(store.get(&[1]).unwrap().as_deref()));
+    assert!(!store.compare_and_swap(&[1], None, Some(&[1])).unwrap());
+
+    // the errors of the store of the plugin are returned by the loader
+    let options = serde_json::json!({"library": library(), "options": {"cache_size": 1}});
+    assert!(Store::open("", &options).is_err());
+}